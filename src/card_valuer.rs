@@ -0,0 +1,226 @@
+use crate::def::*;
+use crate::planner::*;
+use crate::project_model::*;
+use crate::stockpile::*;
+
+// WorkAllを使った結果
+// appliedは完了すると見込めるプロジェクトに投入する労働、capturedはそのうち溢れずに役立つ分
+// moneyはcapturedを投入先のプロジェクトごとにwork_moneyで所持金に換算したもの
+// 今使う場合はmoneyで評価し、手持ちにする場合は使う時までにプロジェクトが変わるのでappliedで評価する
+#[derive(Debug, Clone, Default)]
+pub struct WorkAllPlay {
+    pub completed_now: Vec<usize>,
+    pub completed_later: usize,
+    pub reward_now: i64,
+    pub applied: i64,
+    pub captured: i64,
+    pub money: f64,
+    pub wasted: i64,
+}
+
+// カードの価値を所持金の単位で見積もる
+// 今WorkSingleやWorkAllを使う場合は、投入先のプロジェクトのvのうち投入した労働の割合の分を得るとみなす
+// 手持ちのカードの労働は投入先が決まらないので、E[w] = E[p] から労働1単位を所持金1単位とみなす
+// どちらも価格pとそのまま比較できる
+// 溢れた労働は今使う場合は確定した損失、手持ちにする場合は避けられる可能性があるので重みを変える
+pub struct CardValuer<'a> {
+    state: &'a State,
+    param: &'a Param,
+    project_model: &'a ProjectModel,
+    stock_plan: StockPlan,
+    t: usize,
+}
+
+impl<'a> CardValuer<'a> {
+    pub fn new(
+        state: &'a State,
+        param: &'a Param,
+        project_model: &'a ProjectModel,
+        stock_plan: StockPlan,
+        t: usize,
+    ) -> CardValuer<'a> {
+        CardValuer {
+            state,
+            param,
+            project_model,
+            stock_plan,
+            t,
+        }
+    }
+
+    // 残りのターンで投入できる労働の見積もり
+    pub fn remain_w(&self, p: i64) -> i64 {
+        (999 - self.t as i64) * 2_i64.pow(self.state.invest_level as u32) + (self.state.score - p)
+    }
+
+    // 今使う場合の価値と対象のプロジェクト
+    pub fn use_value(&self, card: &Card) -> (f64, usize) {
+        match card {
+            Card::WorkSingle(w) => {
+                let m = self
+                    .plan_target(card, *w)
+                    .unwrap_or_else(|| self.work_single_target(*w, 0));
+                if self.state.projects[m].h > w + self.remain_w(0) {
+                    return (-INF, m);
+                }
+                let waste = (w - self.state.projects[m].h).max(0) as f64;
+                (
+                    self.work_money(*w, m) - waste * self.param.overflow_alpha.at(self.t),
+                    m,
+                )
+            }
+            Card::WorkAll(w) => {
+                let play = self.work_all_play(*w, 0);
+                (
+                    play.money - play.wasted as f64 * self.param.overflow_alpha.at(self.t),
+                    0,
+                )
+            }
+            Card::CancelSingle | Card::CancelAll => self.cancel_value(card),
+            Card::Invest | Card::None => (-INF, 0),
+        }
+    }
+
+    // 価格pで買って手持ちに加える場合の価値
+    pub fn hold_value(&self, card: &Card, p: i64) -> f64 {
+        match card {
+            Card::WorkSingle(w) => {
+                let m = self.work_single_target(*w, p);
+                if self.state.projects[m].h > w + self.remain_w(p) && p > 0 {
+                    return -INF;
+                }
+                let waste = (w - self.state.projects[m].h).max(0) as f64;
+                *w as f64 - waste * self.param.overflow_alpha_refill.at(self.t) - p as f64
+            }
+            Card::WorkAll(w) => {
+                let play = self.work_all_play(*w, p);
+                play.applied as f64
+                    - play.wasted as f64 * self.param.overflow_alpha_all_refill.at(self.t)
+                    - p as f64
+            }
+            Card::CancelSingle | Card::CancelAll => self.cancel_value(card).0 - p as f64,
+            Card::Invest | Card::None => -INF,
+        }
+    }
+
+    // 使った時に溢れる労働（対象は今のプロジェクト）
+    pub fn overflow(&self, card: &Card, p: i64) -> f64 {
+        match card {
+            Card::WorkSingle(w) => {
+                let m = self.work_single_target(*w, p);
+                (w - self.state.projects[m].h).max(0) as f64
+            }
+            Card::WorkAll(w) => self.work_all_play(*w, p).wasted as f64,
+            _ => 0.,
+        }
+    }
+
+    // 手札のカードを今使う対象を数ターン先まで計画して決める、計画で何も完了しない場合はNone
+    // plan_horizonが0なら計画せず、1ターン分の式で決める
+    fn plan_target(&self, card: &Card, w: i64) -> Option<usize> {
+        if self.param.plan_horizon == 0 {
+            return None;
+        }
+        let slot = self.state.cards.iter().position(|c| c == card);
+        let planner = Planner::new(
+            self.state,
+            slot,
+            self.param.plan_horizon,
+            self.param.overflow_alpha.at(self.t),
+            self.param.work_single_beta.at(self.t),
+        );
+        planner
+            .target(w, |m| !self.is_excluded(m, w, 0))
+            .map(|(m, _)| m)
+    }
+
+    // 労働wをプロジェクトmに投入して得る所持金、完了しない分は進んだ割合だけvを得たとみなす
+    fn work_money(&self, w: i64, m: usize) -> f64 {
+        let p = &self.state.projects[m];
+        w.min(p.h) as f64 * p.v as f64 / p.h as f64
+    }
+
+    fn is_excluded(&self, m: usize, w: i64, p: i64) -> bool {
+        let h = self.state.projects[m].h;
+        // 残りのターンで終わらせられないプロジェクトと、貯めていてこのカードで終わらせられないプロジェクトは対象にしない
+        h > w + self.remain_w(p) || (self.stock_plan.is_held(m) && h > w)
+    }
+
+    fn work_single_target(&self, w: i64, p: i64) -> usize {
+        (0..self.state.projects.len())
+            .max_by_key(|&i| {
+                if self.is_excluded(i, w, p) {
+                    return -INF as i64 - self.state.projects[i].h;
+                }
+                (((w as f64 / self.state.projects[i].h as f64)
+                    .min(1.)
+                    .powf(self.param.work_single_beta.at(self.t))
+                    * self.state.projects[i].v as f64
+                    - ((w - self.state.projects[i].h).max(0) as f64))
+                    * 10000.) as i64
+            })
+            .unwrap()
+    }
+
+    // WorkAllを今のプロジェクトに使った場合の結果
+    // 今完了しないプロジェクトは、残りの労働で必要な労働の少ない順に完了できるだけ完了すると見込む
+    pub fn work_all_play(&self, w: i64, p: i64) -> WorkAllPlay {
+        let mut play = WorkAllPlay::default();
+        let mut needs = vec![];
+        for (m, proj) in self.state.projects.iter().enumerate() {
+            if w >= proj.h {
+                play.completed_now.push(m);
+                play.reward_now += proj.v;
+                play.applied += w;
+                play.captured += proj.h;
+                play.money += proj.v as f64;
+                play.wasted += w - proj.h;
+            } else {
+                needs.push((proj.h - w, m));
+            }
+        }
+        needs.sort();
+        let mut remain_w = self.remain_w(p);
+        for (need, m) in needs {
+            if need > remain_w {
+                break;
+            }
+            remain_w -= need;
+            play.completed_later += 1;
+            play.applied += w;
+            play.captured += w;
+            play.money += self.work_money(w, m);
+        }
+        play
+    }
+
+    fn cancel_value(&self, card: &Card) -> (f64, usize) {
+        if self.t >= self.param.cancel_limit {
+            return (-INF, 0);
+        }
+        match card {
+            Card::CancelSingle => (0..self.state.projects.len())
+                .map(|m| {
+                    let value = self
+                        .project_model
+                        .cancel_value(&self.state.projects[m], self.state.invest_level);
+                    (value, m)
+                })
+                .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+                .unwrap(),
+            Card::CancelAll => {
+                let value = self
+                    .state
+                    .projects
+                    .iter()
+                    .map(|proj| {
+                        self.project_model
+                            .cancel_value(proj, self.state.invest_level)
+                    })
+                    .sum::<f64>();
+                (value, 0)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
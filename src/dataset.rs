@@ -0,0 +1,189 @@
+use std::io::Write;
+
+use crate::def::*;
+use crate::expectimax::*;
+use crate::learned::*;
+use crate::param_table::*;
+use crate::project_model::*;
+use crate::simulator::*;
+use crate::solver::*;
+use crate::trace::*;
+
+// シミュレーションしたゲームのカードを使う選択肢と補充の選択肢ごとに、モンテカルロで最終スコアを推定して書き出す
+// phaseはuseかrefill、useの行はchoiceが手札の位置で、offersと特徴量は空にする
+// usage: dataset <out.csv> [games] [rounds] [interval]
+pub fn run(args: &[String], param_table: &ParamTable, evaluator: &Evaluator) {
+    let path = args.first().map(|s| s.as_str()).unwrap_or("dataset.csv");
+    let games = args.get(1).map(|s| s.parse().unwrap()).unwrap_or(10);
+    let rounds = args.get(2).map(|s| s.parse().unwrap()).unwrap_or(20);
+    let interval = args.get(3).map(|s| s.parse().unwrap()).unwrap_or(50);
+    export_dataset(path, games, rounds, interval, param_table, evaluator);
+}
+
+pub fn export_dataset(
+    path: &str,
+    games: u64,
+    rounds: usize,
+    interval: usize,
+    param_table: &ParamTable,
+    evaluator: &Evaluator,
+) {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+    writeln!(
+        file,
+        "seed,turn,phase,n,m,k,money,invest_level,hand,projects,offers,choice,card_t,card_w,price,features,mc_score,delta"
+    )
+    .unwrap();
+
+    for seed in 0..games {
+        let game = Game::generate(seed);
+        let input = &game.input;
+        let mut interactor = MockInteractor::from_game(&game);
+        let mut solver = Solver {
+            state: game.initial_state(),
+            param: param_table.lookup(input),
+            project_model: ProjectModel::new(),
+            evaluator: evaluator.clone(),
+            verbosity: 0,
+            policy: Policy::Greedy,
+        };
+        let mut recorder = Recorder::new();
+
+        for t in 0..input.t {
+            let action = solver.select_use_card(t);
+            // 最初のターンはまだ補充の候補を見ていないので、カードの出現率が分からない
+            if t % interval == 0 && t > 0 {
+                let mc_scores: Vec<Option<i64>> = solver
+                    .state
+                    .cards
+                    .iter()
+                    .enumerate()
+                    .map(|(c, card)| {
+                        let (value, m) = solver.eval(card, t);
+                        if value <= -INF && Some(c) != action.slot() {
+                            return None;
+                        }
+                        let scores = montecarlo_use(
+                            rounds,
+                            &solver,
+                            input,
+                            t,
+                            &recorder.x,
+                            Action::use_card(c, *card, m),
+                        );
+                        Some(scores.iter().sum::<i64>() / rounds as i64)
+                    })
+                    .collect();
+                let base_score = mc_scores[action.slot().unwrap()].unwrap();
+                for (c, card) in solver.state.cards.iter().enumerate() {
+                    let Some(mc_score) = mc_scores[c] else {
+                        continue;
+                    };
+                    writeln!(
+                        file,
+                        "{},{},use,{},{},{},{},{},{},{},,{},{},{},0,,{},{}",
+                        seed,
+                        t,
+                        input.n,
+                        input.m,
+                        input.k,
+                        solver.state.score,
+                        solver.state.invest_level,
+                        format_cards(&solver.state.cards),
+                        format_projects(&solver.state.projects),
+                        c,
+                        card.to_t(),
+                        card_w(card),
+                        mc_score,
+                        mc_score - base_score,
+                    )
+                    .unwrap();
+                }
+            }
+            if let Action::Invest { .. } = action {
+                solver.state.last_invest_round = t;
+            }
+            solver.state.use_card(action, &mut interactor);
+
+            let new_cards = solver.state.read_status(input, &mut interactor);
+            for offer in new_cards.iter() {
+                recorder.x[offer.card.to_t()] += 1;
+            }
+            let new_card = if t < input.t - 1 {
+                solver.select_new_card(&new_cards, t)
+            } else {
+                0
+            };
+
+            if t % interval == 0 && t < input.t - 1 {
+                let mc_scores: Vec<Option<i64>> = (0..new_cards.len())
+                    .map(|i| {
+                        if new_cards[i].price > solver.state.score {
+                            return None;
+                        }
+                        Some(montecarlo(
+                            rounds,
+                            &solver,
+                            input,
+                            t,
+                            &recorder.x,
+                            true,
+                            i,
+                            &new_cards,
+                        ))
+                    })
+                    .collect();
+                let base_score = mc_scores[new_card].unwrap();
+                for (i, &Offer { card, price: p }) in new_cards.iter().enumerate() {
+                    let Some(mc_score) = mc_scores[i] else {
+                        continue;
+                    };
+                    writeln!(
+                        file,
+                        "{},{},refill,{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                        seed,
+                        t,
+                        input.n,
+                        input.m,
+                        input.k,
+                        solver.state.score,
+                        solver.state.invest_level,
+                        format_cards(&solver.state.cards),
+                        format_projects(&solver.state.projects),
+                        format_offers(&new_cards),
+                        i,
+                        card.to_t(),
+                        card_w(&card),
+                        p,
+                        format_features(&solver, &card, p, t),
+                        mc_score,
+                        mc_score - base_score,
+                    )
+                    .unwrap();
+                }
+            }
+
+            solver
+                .state
+                .refill_card(new_card, &new_cards, &mut interactor);
+        }
+        eprintln!("seed {}: score {}", seed, solver.state.score);
+    }
+}
+
+// 学習用の特徴量、評価関数に使われないカードは空にする
+fn format_features(solver: &Solver, card: &Card, p: i64, t: usize) -> String {
+    if *card == Card::Invest {
+        return String::new();
+    }
+    let valuer = solver.valuer(t);
+    let value = valuer.hold_value(card, p);
+    if value <= -INF {
+        return String::new();
+    }
+    features(&valuer, &solver.state, card, p, t, value)
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
@@ -13,7 +13,7 @@ pub enum Card {
     None,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Project {
     pub h: i64,
     pub v: i64,
@@ -32,7 +32,7 @@ impl Card {
         }
     }
 
-    pub fn to_t(&self) -> usize {
+    pub fn to_t(self) -> usize {
         match self {
             Card::WorkSingle(_) => 0,
             Card::WorkAll(_) => 1,
@@ -44,6 +44,116 @@ impl Card {
     }
 }
 
+// 補充の候補、価格は増資レベルで倍にした後のもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offer {
+    pub card: Card,
+    pub price: i64,
+}
+
+// 1ターンの出力、slotは手札の位置、offerは補充の候補の位置
+// 対象を取らないカードは出力ではm=0になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    UseSingle { slot: usize, project: usize },
+    UseAll { slot: usize },
+    Invest { slot: usize },
+    Refill { offer: usize },
+}
+
+impl Action {
+    // 手札のカードの種類に合わせた行動、対象を取らないカードではprojectを無視する
+    pub fn use_card(slot: usize, card: Card, project: usize) -> Action {
+        match card {
+            Card::WorkSingle(_) | Card::CancelSingle => Action::UseSingle { slot, project },
+            Card::WorkAll(_) | Card::CancelAll => Action::UseAll { slot },
+            Card::Invest => Action::Invest { slot },
+            Card::None => panic!("slot {slot} is empty"),
+        }
+    }
+
+    // 出力の c m を手札と照らし合わせて行動にする
+    pub fn parse_use(c: usize, m: usize, cards: &[Card]) -> Result<Action, String> {
+        match cards.get(c) {
+            None => Err(format!("card {c} is out of range (0..{})", cards.len())),
+            Some(Card::None) => Err(format!("card {c} is empty")),
+            Some(&card) => match Action::use_card(c, card, m) {
+                Action::UseSingle { .. } => Ok(Action::UseSingle {
+                    slot: c,
+                    project: m,
+                }),
+                action if m == 0 => Ok(action),
+                _ => Err(format!("m must be 0 for {:?}, found {m}", card)),
+            },
+        }
+    }
+
+    pub fn slot(&self) -> Option<usize> {
+        match *self {
+            Action::UseSingle { slot, .. } | Action::UseAll { slot } | Action::Invest { slot } => {
+                Some(slot)
+            }
+            Action::Refill { .. } => None,
+        }
+    }
+
+    // 出力でのm
+    pub fn project(&self) -> usize {
+        match *self {
+            Action::UseSingle { project, .. } => project,
+            _ => 0,
+        }
+    }
+
+    // 公式のルールに従っているか、offersは補充する時だけ使う
+    pub fn validate(&self, input: &Input, state: &State, offers: &[Offer]) -> Result<(), String> {
+        match *self {
+            Action::Refill { offer } => match offers.get(offer) {
+                None => Err(format!(
+                    "refill {offer} is out of range (0..{})",
+                    offers.len()
+                )),
+                Some(o) if o.price > state.score => Err(format!(
+                    "refill {offer} costs {} but only {} is available",
+                    o.price, state.score
+                )),
+                Some(_) => Ok(()),
+            },
+            _ => {
+                let slot = self.slot().unwrap();
+                let card = match state.cards.get(slot) {
+                    None | Some(Card::None) => return Err(format!("card {slot} is not in hand")),
+                    Some(&card) => card,
+                };
+                if Action::use_card(slot, card, self.project()) != *self {
+                    return Err(format!("{:?} does not match card {:?}", self, card));
+                }
+                match *self {
+                    Action::UseSingle { project, .. } if project >= input.m => Err(format!(
+                        "project {project} is out of range (0..{})",
+                        input.m
+                    )),
+                    Action::Invest { .. } if state.invest_level >= MAX_INVEST_LEVEL => {
+                        Err(format!("invest beyond level {MAX_INVEST_LEVEL}"))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+// 公式の出力形式
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Action::Refill { offer } => write!(f, "{}", offer),
+            _ => write!(f, "{} {}", self.slot().unwrap(), self.project()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Input {
     pub n: usize,
     pub m: usize,
@@ -51,24 +161,114 @@ pub struct Input {
     pub t: usize,
 }
 
-#[derive(Clone, Copy)]
+// ゲームの進行度 t に対して区分線形に変化する値
+// SCHEDULE_KNOTS のターンでの値を持つ
+// ノットを変えるとパラメータテーブルとチェックポイントの次元が変わるので param_table.rs の説明も合わせる
+pub const SCHEDULE_KNOTS: [usize; 3] = [0, 500, 1000];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule(pub [f64; SCHEDULE_KNOTS.len()]);
+
+impl Schedule {
+    pub fn at(&self, t: usize) -> f64 {
+        for i in 1..SCHEDULE_KNOTS.len() {
+            if t <= SCHEDULE_KNOTS[i] {
+                let (t0, t1) = (SCHEDULE_KNOTS[i - 1], SCHEDULE_KNOTS[i]);
+                let r = (t.max(t0) - t0) as f64 / (t1 - t0) as f64;
+                return self.0[i - 1] + (self.0[i] - self.0[i - 1]) * r;
+            }
+        }
+        self.0[SCHEDULE_KNOTS.len() - 1]
+    }
+}
+
+// 溢れた労働の重みは、使う場合はWorkSingleとWorkAllで共通にする（どちらも所持金の単位なので）
+// 手持ちにする場合は、WorkAllの労働は後で完了するプロジェクトの分も数えるので分ける
+#[derive(Debug, Clone, Copy)]
 pub struct Param {
-    pub overflow_alpha_refill: f64,
-    pub overflow_alpha: f64,
-    pub overflow_alpha_all_refill: f64,
-    pub overflow_alpha_all: f64,
-    pub cancel_alpha_refill: f64,
-    pub cancel_alpha: f64,
-    pub cancel_alpha_all_refill: f64,
-    pub cancel_alpha_all: f64,
+    pub overflow_alpha: Schedule,
+    pub overflow_alpha_refill: Schedule,
+    pub overflow_alpha_all_refill: Schedule,
     pub invest_limit: usize,
     pub cancel_limit: usize,
     pub invest_cost: i64,
-    pub work_single_beta: f64,
+    pub work_single_beta: Schedule,
+    pub hand_weight: Schedule,
+    pub hand_decay: f64,
+    pub stockpile_h: i64,
+    pub plan_horizon: usize,
+}
+
+impl Param {
+    pub const NAMES: [&'static str; 11] = [
+        "overflow_alpha",
+        "overflow_alpha_refill",
+        "overflow_alpha_all_refill",
+        "invest_limit",
+        "cancel_limit",
+        "invest_cost",
+        "work_single_beta",
+        "hand_weight",
+        "hand_decay",
+        "stockpile_h",
+        "plan_horizon",
+    ];
+
+    // to_vec での各パラメータの次元、スケジュールはノットの数
+    pub const DIMS: [usize; 11] = [
+        SCHEDULE_KNOTS.len(),
+        SCHEDULE_KNOTS.len(),
+        SCHEDULE_KNOTS.len(),
+        1,
+        1,
+        1,
+        SCHEDULE_KNOTS.len(),
+        SCHEDULE_KNOTS.len(),
+        1,
+        1,
+        1,
+    ];
+
+    pub fn to_vec(self) -> Vec<f64> {
+        let mut x = vec![];
+        x.extend(self.overflow_alpha.0);
+        x.extend(self.overflow_alpha_refill.0);
+        x.extend(self.overflow_alpha_all_refill.0);
+        x.push(self.invest_limit as f64);
+        x.push(self.cancel_limit as f64);
+        x.push(self.invest_cost as f64);
+        x.extend(self.work_single_beta.0);
+        x.extend(self.hand_weight.0);
+        x.push(self.hand_decay);
+        x.push(self.stockpile_h as f64);
+        x.push(self.plan_horizon as f64);
+        x
+    }
+
+    // 整数のパラメータは丸める
+    pub fn from_vec(x: &[f64]) -> Param {
+        let k = SCHEDULE_KNOTS.len();
+        let schedule = |i: usize| Schedule(std::array::from_fn(|j| x[i + j]));
+        Param {
+            overflow_alpha: schedule(0),
+            overflow_alpha_refill: schedule(k),
+            overflow_alpha_all_refill: schedule(2 * k),
+            invest_limit: x[3 * k].round() as usize,
+            cancel_limit: x[3 * k + 1].round() as usize,
+            invest_cost: x[3 * k + 2].round() as i64,
+            work_single_beta: schedule(3 * k + 3),
+            hand_weight: schedule(4 * k + 3),
+            hand_decay: x[5 * k + 3],
+            stockpile_h: x[5 * k + 4].round() as i64,
+            plan_horizon: x[5 * k + 5].round() as usize,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct State {
+    // 補充した回数、整合性のエラーの報告に使う
+    pub turn: usize,
     pub last_invest_round: usize,
     pub invest_level: usize,
     pub score: i64,
@@ -77,34 +277,121 @@ pub struct State {
 }
 
 impl State {
-    pub fn read_status<I: Interactor>(
-        &mut self,
-        input: &Input,
-        interactor: &mut I,
-    ) -> Vec<(Card, i64)> {
-        let (projects, score, new_cards) = interactor.read_status(input);
+    pub fn read_status<I: Interactor>(&mut self, input: &Input, interactor: &mut I) -> Vec<Offer> {
+        let (projects, score, offers) = interactor.read_status(input);
         self.projects = projects;
         self.score = score;
-        new_cards
+        if cfg!(feature = "debug") {
+            self.assert_invariants(self.check_invariants(1).and(self.check_offers(&offers)));
+        }
+        offers
     }
 
-    pub fn use_card<I: Interactor>(&mut self, use_card: usize, m: usize, interactor: &mut I) {
-        interactor.output_c(use_card, m);
-        if let Card::Invest = self.cards[use_card] {
+    pub fn use_card<I: Interactor>(&mut self, action: Action, interactor: &mut I) {
+        interactor.output(action);
+        let slot = action.slot().unwrap();
+        if let Card::Invest = self.cards[slot] {
             self.invest_level += 1;
         }
-        self.cards[use_card] = Card::None;
+        self.cards[slot] = Card::None;
+        if cfg!(feature = "debug") {
+            self.assert_invariants(self.check_invariants(1));
+        }
     }
 
     pub fn refill_card<I: Interactor>(
         &mut self,
-        selected_card: usize,
-        new_cards: &Vec<(Card, i64)>,
+        offer: usize,
+        offers: &[Offer],
         interactor: &mut I,
     ) {
-        interactor.output_r(selected_card);
+        interactor.output(Action::Refill { offer });
         let i = self.empty_card_index().unwrap();
-        self.cards[i] = new_cards[selected_card].0;
+        self.cards[i] = offers[offer].card;
+        if cfg!(feature = "debug") {
+            self.assert_invariants(self.check_invariants(0));
+        }
+        self.turn += 1;
+    }
+
+    // actionを使った後の状態を予測する
+    // state.scoreは前のターンの補充の支払い前なので、使う前の所持金はmoneyで渡す
+    pub fn predict(&self, action: Action, money: i64) -> Prediction {
+        let mut projects: Vec<Option<Project>> = self.projects.iter().copied().map(Some).collect();
+        let mut money = money;
+        let mut work = |project: &mut Option<Project>, w: i64| {
+            if let Some(p) = project {
+                p.h -= w;
+                if p.h <= 0 {
+                    money += p.v;
+                    *project = None;
+                }
+            }
+        };
+        match action {
+            Action::UseSingle { slot, project } => match self.cards[slot] {
+                Card::WorkSingle(w) => work(&mut projects[project], w),
+                _ => projects[project] = None,
+            },
+            Action::UseAll { slot } => match self.cards[slot] {
+                Card::WorkAll(w) => projects.iter_mut().for_each(|p| work(p, w)),
+                _ => projects.iter_mut().for_each(|p| *p = None),
+            },
+            Action::Invest { .. } | Action::Refill { .. } => {}
+        }
+        Prediction { projects, money }
+    }
+
+    // emptyは空きの数で、カードを使ってから補充するまでは1、それ以外は0
+    pub fn check_invariants(&self, empty: usize) -> Result<(), String> {
+        let count = self
+            .cards
+            .iter()
+            .filter(|&&card| card == Card::None)
+            .count();
+        if count != empty {
+            return Err(format!("expected {empty} empty slots, found {count}"));
+        }
+        if self.invest_level > MAX_INVEST_LEVEL {
+            return Err(format!(
+                "invest level {} exceeds {MAX_INVEST_LEVEL}",
+                self.invest_level
+            ));
+        }
+        if self.score < 0 {
+            return Err(format!("negative money {}", self.score));
+        }
+        if let Some(m) = self.projects.iter().position(|p| p.h <= 0) {
+            return Err(format!(
+                "project {m} has non-positive h {}",
+                self.projects[m].h
+            ));
+        }
+        Ok(())
+    }
+
+    // 補充の候補は今の増資レベルで倍になっている
+    pub fn check_offers(&self, offers: &[Offer]) -> Result<(), String> {
+        let scale = 1 << self.invest_level;
+        for (r, offer) in offers.iter().enumerate() {
+            let w = match offer.card {
+                Card::WorkSingle(w) | Card::WorkAll(w) => w,
+                _ => scale,
+            };
+            if w % scale != 0 || offer.price % scale != 0 {
+                return Err(format!(
+                    "offer {r} {:?} p={} is not scaled by 2^{}",
+                    offer.card, offer.price, self.invest_level
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn assert_invariants(&self, result: Result<(), String>) {
+        if let Err(e) = result {
+            panic!("invariant violated at turn {}: {e}", self.turn);
+        }
     }
 
     pub fn empty_card_index(&self) -> Option<usize> {
@@ -117,6 +404,44 @@ impl State {
     }
 }
 
+// カードを使った後にジャッジが返すはずのプロジェクトと所持金
+// 完了したかキャンセルしたプロジェクトは新しく引くので分からず、None
+#[derive(Debug, Clone)]
+pub struct Prediction {
+    pub projects: Vec<Option<Project>>,
+    pub money: i64,
+}
+
+impl Prediction {
+    // ジャッジの返した値と比べて、食い違いを全て返す
+    pub fn check(&self, projects: &[Project], money: i64) -> Vec<String> {
+        let mut mismatches = vec![];
+        if projects.len() != self.projects.len() {
+            mismatches.push(format!(
+                "expected {} projects, found {}",
+                self.projects.len(),
+                projects.len()
+            ));
+        }
+        for (m, (expected, actual)) in self.projects.iter().zip(projects.iter()).enumerate() {
+            match expected {
+                Some(p) if (p.h, p.v) != (actual.h, actual.v) => mismatches.push(format!(
+                    "project {m}: expected {}:{}, found {}:{}",
+                    p.h, p.v, actual.h, actual.v
+                )),
+                None if actual.h <= 0 => {
+                    mismatches.push(format!("project {m}: new project has h {}", actual.h))
+                }
+                _ => {}
+            }
+        }
+        if money != self.money {
+            mismatches.push(format!("money: expected {}, found {}", self.money, money));
+        }
+        mismatches
+    }
+}
+
 pub struct Recorder {
     pub scores: Vec<i64>,
     pub invest_rounds: Vec<usize>,
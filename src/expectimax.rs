@@ -0,0 +1,362 @@
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+
+use crate::def::*;
+use crate::simulator::*;
+use crate::solver::*;
+use crate::util::*;
+
+// 全体の時間制限、超えそうなターンは貪欲に選ぶ
+const TIME_LIMIT: f64 = 1.8;
+
+// 終盤のモンテカルロの補充（Solver::select_refill）のために残す時間
+// seed 100-139ではMONTE_CARLO_TURN以降に最大0.42秒かかった
+const MONTE_CARLO_RESERVE: f64 = 0.5;
+
+// 貪欲の選択を探索の結果で置き換えるのに必要な、改善の平均の標準誤差に対する倍率
+const GAIN_Z: f64 = 2.;
+
+// カードの使用と補充の選び方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Greedy,
+    Expectimax(Expectimax),
+}
+
+impl Policy {
+    // "greedy" か "expectimax[:depth[:width[:samples]]]"
+    pub fn parse(s: &str) -> Result<Policy, String> {
+        let mut tokens = s.split(':');
+        match tokens.next() {
+            Some("greedy") => Ok(Policy::Greedy),
+            Some("expectimax") => {
+                let mut search = Expectimax::default();
+                for (i, token) in tokens.enumerate() {
+                    let value = token
+                        .parse()
+                        .map_err(|_| format!("invalid policy: `{s}`"))?;
+                    match i {
+                        0 => search.depth = value,
+                        1 => search.width = value,
+                        2 => search.samples = value,
+                        _ => return Err(format!("invalid policy: `{s}`")),
+                    }
+                }
+                if search.depth == 0 || search.width == 0 || search.samples == 0 {
+                    return Err(format!("invalid policy: `{s}`"));
+                }
+                Ok(Policy::Expectimax(search))
+            }
+            _ => Err(format!("invalid policy: `{s}`")),
+        }
+    }
+}
+
+// depthターン先までの期待値最大化の探索
+// 自分の手番ではevalの上位width個の使い方と、eval_refillの上位width個の補充を候補にする
+// 偶然手番では、新しいプロジェクトと補充の候補をシミュレータの生成器とxでsamples回引く
+// 増資の規則が当てはまる場合と、補充をモンテカルロで選ぶ終盤は探索せずに貪欲に選ぶ
+// 根では貪欲の使い方と各候補を同じ乱数列で比べ、改善が有意な場合だけ貪欲から変える
+// 各ノードでターンの締め切りを確かめ、過ぎたら以降のノードは葉の評価にして、その候補は比べずに打ち切る
+// 根の補充は探索せず貪欲に選ぶ（理由はSolver::choose_refill）
+// 葉の評価は所持金と、手札の増資以外のカードを無料で手持ちにする価値と、プロジェクトの利益の和
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expectimax {
+    pub depth: usize,
+    pub width: usize,
+    pub samples: usize,
+}
+
+impl Default for Expectimax {
+    fn default() -> Expectimax {
+        Expectimax {
+            depth: 3,
+            width: 2,
+            samples: 8,
+        }
+    }
+}
+
+impl Expectimax {
+    pub fn select_use_card(
+        &self,
+        solver: &mut Solver,
+        input: &Input,
+        t: usize,
+        x: &[i64],
+        money: i64,
+    ) -> Action {
+        // 探索の中では所持金をstate.scoreで扱うので、前のターンの補充の支払いを引いておく
+        let score = solver.state.score;
+        solver.state.score = money;
+        let action = self.search_use_card(solver, input, t, x);
+        solver.state.score = score;
+        action
+    }
+
+    fn search_use_card(&self, solver: &mut Solver, input: &Input, t: usize, x: &[i64]) -> Action {
+        let greedy = solver.select_use_card(t);
+        let deadline = self.deadline(t);
+        if matches!(greedy, Action::Invest { .. })
+            || t >= MONTE_CARLO_TURN
+            || time::elapsed_seconds() >= deadline
+        {
+            return greedy;
+        }
+        let mut search = Search::new(self, input, x, deadline);
+        search.reseed();
+        let base = search.use_samples(solver, greedy, t, self.depth);
+        if search.timed_out {
+            return greedy;
+        }
+        let mut best = (greedy, 0.);
+        for action in search.use_candidates(solver, t) {
+            if action == greedy {
+                continue;
+            }
+            search.reseed();
+            let samples = search.use_samples(solver, action, t, self.depth);
+            if search.timed_out {
+                break;
+            }
+            let diffs: Vec<f64> = samples
+                .iter()
+                .zip(base.iter())
+                .map(|(a, b)| a - b)
+                .collect();
+            let gain = significant_gain(&diffs);
+            if gain > best.1 {
+                best = (action, gain);
+            }
+        }
+        best.0
+    }
+
+    // ターンtの探索の締め切り、モンテカルロの補充の分を残して、それまでのターンの進み具合に比例させる
+    fn deadline(&self, t: usize) -> f64 {
+        (TIME_LIMIT - MONTE_CARLO_RESERVE) * (t + 1) as f64 / MONTE_CARLO_TURN as f64
+    }
+}
+
+struct Search<'a> {
+    config: &'a Expectimax,
+    input: &'a Input,
+    // 観測した種類ごとの枚数に1を足したもの、序盤は観測がないので
+    x: Vec<i64>,
+    seed: u64,
+    rng: ChaCha20Rng,
+    deadline: f64,
+    // 締め切りを過ぎて、葉の評価で打ち切ったノードがあるか
+    timed_out: bool,
+}
+
+impl<'a> Search<'a> {
+    fn new(config: &'a Expectimax, input: &'a Input, x: &'a [i64], deadline: f64) -> Search<'a> {
+        let seed = rnd::next() as u64;
+        Search {
+            config,
+            input,
+            x: x.iter().map(|&x| x + 1).collect(),
+            seed,
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            deadline,
+            timed_out: false,
+        }
+    }
+
+    // 候補ごとに同じ乱数列で偶然手番を引いて、比較のぶれを抑える
+    fn reseed(&mut self) {
+        self.rng = ChaCha20Rng::seed_from_u64(self.seed);
+    }
+
+    fn use_candidates(&self, solver: &Solver, t: usize) -> Vec<Action> {
+        let mut evals: Vec<(f64, Action)> = solver
+            .state
+            .cards
+            .iter()
+            .enumerate()
+            .filter(|(_, card)| **card != Card::Invest)
+            .map(|(c, card)| {
+                let (value, m) = solver.eval(card, t);
+                (value, Action::use_card(c, *card, m))
+            })
+            .filter(|(value, _)| *value > -INF)
+            .collect();
+        evals.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        evals
+            .into_iter()
+            .take(self.config.width)
+            .map(|(_, action)| action)
+            .collect()
+    }
+
+    fn refill_candidates(&self, solver: &Solver, offers: &[Offer], t: usize) -> Vec<usize> {
+        let mut evals: Vec<(f64, usize)> = offers
+            .iter()
+            .enumerate()
+            .map(|(r, offer)| (solver.eval_refill(&offer.card, offer.price, t), r))
+            .filter(|(value, _)| *value > -INF)
+            .collect();
+        evals.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        evals
+            .into_iter()
+            .take(self.config.width)
+            .map(|(_, r)| r)
+            .collect()
+    }
+
+    // actionを使った後の偶然手番の期待値
+    fn use_value(&mut self, solver: &mut Solver, action: Action, t: usize, depth: usize) -> f64 {
+        let samples = self.use_samples(solver, action, t, depth);
+        samples.iter().sum::<f64>() / samples.len() as f64
+    }
+
+    // actionを使った後の偶然手番を引くごとの値
+    fn use_samples(
+        &mut self,
+        solver: &mut Solver,
+        action: Action,
+        t: usize,
+        depth: usize,
+    ) -> Vec<f64> {
+        let saved = solver.state.clone();
+        let mut samples = vec![];
+        for _ in 0..self.config.samples {
+            let offers = self.apply_use(solver, action, t);
+            samples.push(
+                self.refill_candidates(solver, &offers, t)
+                    .into_iter()
+                    .map(|r| self.refill_value(solver, &offers, r, t, depth))
+                    .fold(-INF, f64::max),
+            );
+            solver.state = saved.clone();
+        }
+        samples
+    }
+
+    // 補充rをした後の自分の手番の値、depthを使い切ったら葉の評価
+    fn refill_value(
+        &mut self,
+        solver: &mut Solver,
+        offers: &[Offer],
+        r: usize,
+        t: usize,
+        depth: usize,
+    ) -> f64 {
+        let saved = solver.state.clone();
+        let i = solver.state.empty_card_index().unwrap();
+        solver.state.cards[i] = offers[r].card;
+        // 増資は葉で評価しないので、払った分と同じ価値があるとみなす
+        if offers[r].card != Card::Invest {
+            solver.state.score -= offers[r].price;
+        }
+        if !self.timed_out && time::elapsed_seconds() >= self.deadline {
+            self.timed_out = true;
+        }
+        let value = if depth <= 1 || t + 1 >= self.input.t || self.timed_out {
+            self.leaf(solver, t + 1)
+        } else {
+            let greedy = solver.select_use_card(t + 1);
+            let actions = match greedy {
+                Action::Invest { .. } => vec![greedy],
+                _ => self.use_candidates(solver, t + 1),
+            };
+            actions
+                .into_iter()
+                .map(|action| self.use_value(solver, action, t + 1, depth - 1))
+                .fold(-INF, f64::max)
+        };
+        solver.state = saved;
+        value
+    }
+
+    fn leaf(&self, solver: &Solver, t: usize) -> f64 {
+        let state = &solver.state;
+        // 手札のカードは無料で手持ちにする価値
+        // eval_refillは手札に同じカードがあると自身との重なりも引くので使わない
+        let valuer = solver.valuer(t);
+        let cards: f64 = state
+            .cards
+            .iter()
+            .filter(|&&card| card != Card::Invest && card != Card::None)
+            .map(|card| valuer.hold_value(card, 0).max(0.))
+            .sum();
+        // 労働はおよそ1単位あたり1で買えるので、プロジェクトは残りを買って終わらせた時の利益v-hとみなす
+        let projects: f64 = state.projects.iter().map(|p| (p.v - p.h) as f64).sum();
+        state.score as f64 + cards + projects
+    }
+
+    // 完了やキャンセルで入れ替わるプロジェクトと、補充の候補を引く
+    fn apply_use(&mut self, solver: &mut Solver, action: Action, t: usize) -> Vec<Offer> {
+        let state = &mut solver.state;
+        let slot = action.slot().unwrap();
+        let card = state.cards[slot];
+        state.cards[slot] = Card::None;
+        let rng = &mut self.rng;
+        match card {
+            Card::WorkSingle(w) => work_project(state, rng, action.project(), w),
+            Card::WorkAll(w) => {
+                for m in 0..state.projects.len() {
+                    work_project(state, rng, m, w);
+                }
+            }
+            Card::CancelSingle => replace_project(state, rng, action.project()),
+            Card::CancelAll => {
+                for m in 0..state.projects.len() {
+                    replace_project(state, rng, m);
+                }
+            }
+            Card::Invest => {
+                state.invest_level += 1;
+                state.last_invest_round = t;
+            }
+            Card::None => unreachable!(),
+        }
+
+        let scale = 1 << state.invest_level;
+        let mut offers = vec![Offer {
+            card: Card::WorkSingle(scale),
+            price: 0,
+        }];
+        for _ in 1..self.input.k {
+            let mut offer = generate_card(rng, self.input.m, &self.x);
+            offer.card = match offer.card {
+                Card::WorkSingle(w) => Card::WorkSingle(w * scale),
+                Card::WorkAll(w) => Card::WorkAll(w * scale),
+                card => card,
+            };
+            offer.price *= scale;
+            offers.push(offer);
+        }
+        offers
+    }
+}
+
+// 同じ乱数列で引いた偶然手番ごとの差の平均、平均が標準誤差のGAIN_Z倍を超えない場合は0
+fn significant_gain(diffs: &[f64]) -> f64 {
+    let n = diffs.len() as f64;
+    let mean = diffs.iter().sum::<f64>() / n;
+    let var = diffs.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1.).max(1.);
+    if mean > GAIN_Z * (var / n).sqrt() {
+        mean
+    } else {
+        0.
+    }
+}
+
+fn work_project(state: &mut State, rng: &mut ChaCha20Rng, m: usize, w: i64) {
+    state.projects[m].h -= w;
+    if state.projects[m].h <= 0 {
+        state.score += state.projects[m].v;
+        replace_project(state, rng, m);
+    }
+}
+
+fn replace_project(state: &mut State, rng: &mut ChaCha20Rng, m: usize) {
+    let project = generate_project(rng);
+    let scale = 1 << state.invest_level;
+    state.projects[m] = Project {
+        h: project.h * scale,
+        v: project.v * scale,
+    };
+}
@@ -0,0 +1,192 @@
+use crate::def::*;
+use crate::forecast::*;
+use crate::param_table::*;
+use crate::simulator::*;
+use crate::snapshot::*;
+use crate::solver::*;
+use crate::trace::*;
+
+const INVEST_RULES: [&str; 4] = [
+    "all cards in hand are invest",
+    "invest limit reached",
+    "invested last turn",
+    "reaches max invest level",
+];
+
+const REFILL_INVEST_RULES: [&str; 3] = [
+    "cheap for the current level",
+    "hand becomes all invest",
+    "next invest reaches max level",
+];
+
+// トレースのあるターン、またはスナップショットで、ソルバーが各行動をどう評価したかを表示する
+// roundsを指定すると、各行動についてモンテカルロでの最終スコアの平均と95%信頼区間も表示する
+// usage: explain <trace|seed> <turn> [rounds]
+//        explain <snapshot> [rounds]
+pub fn run(args: &[String], param_table: &ParamTable, evaluator: &Evaluator) {
+    let source = args.first().map(|s| s.as_str()).unwrap_or("trace.log");
+    if let Some(snapshot) = load_snapshot(source) {
+        let rounds: usize = args.get(1).map(|s| s.parse().unwrap()).unwrap_or(0);
+        explain(&snapshot, &[], param_table, evaluator, rounds);
+        return;
+    }
+    let turn: usize = args.get(1).map(|s| s.parse().unwrap()).unwrap_or(0);
+    let rounds: usize = args.get(2).map(|s| s.parse().unwrap()).unwrap_or(0);
+
+    let trace = Trace::from_source(source, param_table, evaluator);
+    let Some(record) = trace.turns.get(turn) else {
+        eprintln!("turn {} is out of range (0..{})", turn, trace.turns.len());
+        return;
+    };
+    println!(
+        "trace: use {} on {}, refill {}",
+        record.action.slot().unwrap(),
+        record.action.project(),
+        record.refill
+    );
+    for phase in [Phase::Use, Phase::Refill] {
+        let snapshot = Snapshot::from_trace(&trace, turn, phase).unwrap();
+        explain(
+            &snapshot,
+            &trace.turns[..turn],
+            param_table,
+            evaluator,
+            rounds,
+        );
+    }
+}
+
+// テキスト形式のスナップショットはinputで、JSONは{で始まる
+fn load_snapshot(source: &str) -> Option<Snapshot> {
+    let text = std::fs::read_to_string(source).ok()?;
+    let head = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .find(|line| !line.is_empty())?;
+    if head.starts_with("input") || head.starts_with('{') {
+        Some(Snapshot::load(source).unwrap())
+    } else {
+        None
+    }
+}
+
+// historyはスナップショットより前のターンの記録、収入の見通しに使う
+pub fn explain(
+    snapshot: &Snapshot,
+    history: &[TurnRecord],
+    param_table: &ParamTable,
+    evaluator: &Evaluator,
+    rounds: usize,
+) {
+    let solver = snapshot.solver(param_table, evaluator);
+    let (input, t, x) = (&snapshot.input, snapshot.t, &snapshot.x);
+    println!(
+        "turn {} ({:?}): level {}, money {}, last invest {}",
+        t,
+        snapshot.phase,
+        solver.state.invest_level,
+        solver.state.score,
+        solver.state.last_invest_round
+    );
+    println!("projects: {}", format_projects(&solver.state.projects));
+
+    match snapshot.phase {
+        Phase::Use => {
+            let select_card = solver.select_use_card(t).slot().unwrap();
+            for (c, card) in solver.state.cards.iter().enumerate() {
+                let (value, m) = solver.eval(card, t);
+                let mut line = format!(
+                    "{} {:>2} {:<16} {:>14} m={}",
+                    if c == select_card { '*' } else { ' ' },
+                    c,
+                    format!("{:?}", card),
+                    format_value(value),
+                    m
+                );
+                if let Card::WorkAll(w) = card {
+                    let play = solver.valuer(t).work_all_play(*w, 0);
+                    line += &format!(
+                        " completes {:?} now (+{}), {} later, waste {}",
+                        play.completed_now, play.reward_now, play.completed_later, play.wasted
+                    );
+                }
+                if *card == Card::Invest {
+                    line += &match solver.invest_rule(t) {
+                        Some(rule) => format!(" rule {}: {}", rule, INVEST_RULES[rule - 1]),
+                        None => " no rule".to_string(),
+                    };
+                }
+                if rounds > 0 {
+                    let scores =
+                        montecarlo_use(rounds, &solver, input, t, x, Action::use_card(c, *card, m));
+                    line += &format!(" mc {}", format_estimate(&scores));
+                }
+                println!("{line}");
+            }
+        }
+        Phase::Refill => {
+            let offers = &snapshot.offers;
+            let select_card = solver.select_new_card(offers, t);
+            let forecast = Forecast::new(input, &solver.state, offers, x, history);
+            println!(
+                "forecast: income {:.1}/turn, next invest {} (price ~{:.0}, offered in {}, affordable in {})",
+                forecast.income,
+                format_turns(forecast.turns_to_invest()),
+                forecast.invest_price,
+                format_turns(forecast.turns_to_offer),
+                format_turns(forecast.turns_to_afford)
+            );
+            for (r, Offer { card, price: p }) in offers.iter().enumerate() {
+                let mut line = format!(
+                    "{} {:>2} {:<16} {:>14} p={}",
+                    if r == select_card { '*' } else { ' ' },
+                    r,
+                    format!("{:?}", card),
+                    format_value(solver.eval_refill(card, *p, t)),
+                    p
+                );
+                if *card == Card::Invest {
+                    line += &match solver.refill_invest_rule(*p, t) {
+                        Some(rule) => format!(" rule {}: {}", rule, REFILL_INVEST_RULES[rule - 1]),
+                        None => " no rule".to_string(),
+                    };
+                    line += &format!(
+                        ", payback {}{}",
+                        format_turns(forecast.payback(*p)),
+                        if forecast.is_profitable(*p, input, t) {
+                            ""
+                        } else {
+                            " (not profitable)"
+                        }
+                    );
+                }
+                if rounds > 0 && *p <= solver.state.score {
+                    let scores = montecarlo_refill(rounds, &solver, input, t, x, r, offers);
+                    line += &format!(" mc {}", format_estimate(&scores));
+                }
+                println!("{line}");
+            }
+        }
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value >= INF {
+        "inf".to_string()
+    } else if value <= -INF {
+        "-inf".to_string()
+    } else {
+        format!("{:.3}", value)
+    }
+}
+
+fn format_estimate(scores: &[i64]) -> String {
+    let n = scores.len() as f64;
+    let mean = scores.iter().sum::<i64>() as f64 / n;
+    let var = scores
+        .iter()
+        .map(|&s| (s as f64 - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.).max(1.);
+    format!("{:.0} ± {:.0}", mean, 1.96 * (var / n).sqrt())
+}
@@ -0,0 +1,168 @@
+use crate::def::*;
+use crate::trace::*;
+
+// 増資カードの価格の事前の値、生成の分布 200..=1000 の平均
+// 観測1回分の重みで、これまでに見た増資カードの価格の平均に混ぜる
+const PRIOR_INVEST_PRICE: f64 = 600.;
+
+// 今の増資レベルでの1ターンあたりの収入と、次の増資の見通し
+// 収入は記録のターンごとに完了したプロジェクトのvから補充したカードの価格を引いたもの（増資カードは除く）
+// 増資レベルごとに2^Lで割ってレベル0に換算し、今のレベルの記録があればそれだけを平均する
+// 記録がない序盤は、手札の労働カードとプロジェクトのv/hから見積もる
+// 増資カードの価格は、記録と今の補充の候補に出た増資カードの価格をレベル0に換算して平均する
+// 補充の候補の出やすさはxから推定する
+#[derive(Debug, Clone, Copy)]
+pub struct Forecast {
+    pub income: f64,
+    pub invest_price: f64,
+    // 増資カードが補充の候補に出るまでのターン数の期待値
+    pub turns_to_offer: f64,
+    // 所持金が増資カードの平均の価格に届くまでのターン数
+    pub turns_to_afford: f64,
+}
+
+impl Forecast {
+    // historyはこのターンより前の記録
+    pub fn new(
+        input: &Input,
+        state: &State,
+        offers: &[Offer],
+        x: &[i64],
+        history: &[TurnRecord],
+    ) -> Forecast {
+        let scale = (1_i64 << state.invest_level) as f64;
+
+        // (増資レベル, レベル0に換算した収入)、次のターンの所持金との差に増資カードの購入分を足し戻す
+        // 記録の所持金は前のターンの補充の支払い前なので、差に含まれるのは前の記録の補充の価格（最初のターンは0）
+        let incomes: Vec<(usize, f64)> = history
+            .iter()
+            .enumerate()
+            .zip(history.iter().skip(1))
+            .filter(|((_, record), _)| !matches!(record.action, Action::Invest { .. }))
+            .map(|((i, record), next)| {
+                let refilled = i
+                    .checked_sub(1)
+                    .and_then(|i| history[i].offers.get(history[i].refill));
+                let invest = match refilled {
+                    Some(offer) if offer.card == Card::Invest => offer.price,
+                    _ => 0,
+                };
+                let income = next.money - record.money + invest;
+                (
+                    record.invest_level,
+                    income as f64 / (1_i64 << record.invest_level) as f64,
+                )
+            })
+            .collect();
+        let mean = |values: Vec<f64>| values.iter().sum::<f64>() / values.len() as f64;
+        let current: Vec<f64> = incomes
+            .iter()
+            .filter(|(level, _)| *level == state.invest_level)
+            .map(|(_, income)| *income)
+            .collect();
+        let income = if !current.is_empty() {
+            mean(current) * scale
+        } else if !incomes.is_empty() {
+            mean(incomes.iter().map(|(_, income)| *income).collect()) * scale
+        } else {
+            hand_income(input, state, offers)
+        };
+
+        // 補充の候補はカードを使った後に出るので、使ったカードが増資ならレベルが1つ上がっている
+        let invest_prices: Vec<f64> = history
+            .iter()
+            .map(|record| {
+                let level =
+                    record.invest_level + matches!(record.action, Action::Invest { .. }) as usize;
+                (record.offers.as_slice(), level)
+            })
+            .chain(std::iter::once((offers, state.invest_level)))
+            .flat_map(|(offers, level)| {
+                offers
+                    .iter()
+                    .filter(|offer| offer.card == Card::Invest)
+                    .map(move |offer| offer.price as f64 / (1_i64 << level) as f64)
+            })
+            .collect();
+        let invest_price = (invest_prices.iter().sum::<f64>() + PRIOR_INVEST_PRICE)
+            / (invest_prices.len() + 1) as f64
+            * scale;
+
+        let q = x[4] as f64 / x.iter().sum::<i64>().max(1) as f64;
+        let appear = 1. - (1. - q).powi(input.k as i32 - 1);
+        let turns_to_offer = if appear > 0. { 1. / appear } else { INF };
+        let turns_to_afford = if state.score as f64 >= invest_price {
+            0.
+        } else if income > 0. {
+            (invest_price - state.score as f64) / income
+        } else {
+            INF
+        };
+
+        Forecast {
+            income,
+            invest_price,
+            turns_to_offer,
+            turns_to_afford,
+        }
+    }
+
+    // 次に増資カードを買えるまでのターン数
+    pub fn turns_to_invest(&self) -> f64 {
+        self.turns_to_offer.max(self.turns_to_afford)
+    }
+
+    // 今価格pで増資カードを買った場合に、増資で増えた収入で元が取れるまでのターン数
+    // 増資すると以降の収入は倍になるので1ターンあたりincomeだけ増える
+    // 増資カードを使うターンは労働を投入できないので、その1ターン分の収入も取り戻す必要がある
+    pub fn payback(&self, p: i64) -> f64 {
+        if self.income <= 0. {
+            return INF;
+        }
+        (p as f64 + self.income) / self.income
+    }
+
+    pub fn is_profitable(&self, p: i64, input: &Input, t: usize) -> bool {
+        self.payback(p) < (input.t - t) as f64
+    }
+}
+
+// 記録がない場合の収入の見積もり
+// 手札の労働カードの平均の労働量に、プロジェクトの v/h から補充の候補の p/w を引いた利ざやをかける
+fn hand_income(input: &Input, state: &State, offers: &[Offer]) -> f64 {
+    let scale = (1_i64 << state.invest_level) as f64;
+    let work = |card: &Card| match card {
+        Card::WorkSingle(w) => Some(*w as f64),
+        Card::WorkAll(w) => Some((w * input.m as i64) as f64),
+        _ => None,
+    };
+
+    let works: Vec<f64> = state.cards.iter().filter_map(work).collect();
+    let work_per_turn = if works.is_empty() {
+        scale
+    } else {
+        works.iter().sum::<f64>() / works.len() as f64
+    };
+    let value_per_work = state
+        .projects
+        .iter()
+        .map(|p| p.v as f64 / p.h as f64)
+        .sum::<f64>()
+        / state.projects.len() as f64;
+    // 0番目の候補は常に無料なので除く
+    let (price, offered) = offers
+        .iter()
+        .skip(1)
+        .filter_map(|offer| Some((offer.price as f64, work(&offer.card)?)))
+        .fold((0., 0.), |(p, w), (p1, w1)| (p + p1, w + w1));
+    let price_per_work = if offered > 0. { price / offered } else { 1. };
+    work_per_turn * (value_per_work - price_per_work)
+}
+
+pub fn format_turns(turns: f64) -> String {
+    if turns >= INF {
+        "never".to_string()
+    } else {
+        format!("{:.0} turns", turns)
+    }
+}
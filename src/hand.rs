@@ -0,0 +1,51 @@
+use crate::card_valuer::*;
+use crate::def::*;
+
+// 2枚のカードの役割が重なる度合い（0から1）
+// 1ターンに使えるのは1枚なので、役割が重なるカードを2枚持つと後のものは使う機会が遅れる
+// WorkAllとWorkSingleはどちらも労働を投入するので、種類が違っても重なるとみなす
+// WorkSingle同士は大きさが違うと向いているプロジェクトが違うので、小さい方の割合だけ重なる
+// 増資カードは規則で使う時期が決まるので、どのカードとも重ならないとみなす
+// （先に使って他のカードを遅らせるとみなすと、seed 100-299で重なり1: -0.166、0.5: -0.127）
+fn overlap(a: &Card, b: &Card) -> f64 {
+    match (a, b) {
+        (Card::WorkSingle(x), Card::WorkSingle(y)) => *x.min(y) as f64 / *x.max(y) as f64,
+        (Card::WorkSingle(_) | Card::WorkAll(_), Card::WorkSingle(_) | Card::WorkAll(_)) => 1.,
+        (Card::CancelSingle | Card::CancelAll, Card::CancelSingle | Card::CancelAll) => 1.,
+        _ => 0.,
+    }
+}
+
+// 手札全体の価値、価値の高い順に、先に使うカードと重なる分だけdecayを掛けて割り引いて足す
+// 増資カードは評価関数の外の規則で使うので含めない
+pub fn hand_value(valuer: &CardValuer, hand: &[Card], decay: f64) -> f64 {
+    let mut cards: Vec<(f64, Card)> = vec![];
+    for card in hand {
+        let value = match card {
+            Card::None | Card::Invest => continue,
+            _ => valuer.use_value(card).0,
+        };
+        if value > -INF {
+            cards.push((value, *card));
+        }
+    }
+    cards.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    (0..cards.len())
+        .map(|i| {
+            let delay: f64 = cards[..i]
+                .iter()
+                .map(|(_, c)| overlap(c, &cards[i].1))
+                .sum();
+            cards[i].0.max(0.) * decay.powf(delay)
+        })
+        .sum()
+}
+
+// cardを手札に加えた時の価値の増分と、カード単体の価値との差
+// 重なるカードを既に持っているほど負になる
+pub fn hand_bonus(valuer: &CardValuer, hand: &[Card], card: &Card, decay: f64) -> f64 {
+    let mut new_hand = hand.to_vec();
+    new_hand.push(*card);
+    let (value, _) = valuer.use_value(card);
+    hand_value(valuer, &new_hand, decay) - hand_value(valuer, hand, decay) - value.max(0.)
+}
@@ -0,0 +1,284 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::judge::*;
+use crate::simulator::*;
+use crate::trace::*;
+
+// ゲームの終了後にソルバーが終了するのを待つ時間
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+// 1ゲーム分の結果、不正な出力や時間切れの場合はスコア0
+pub struct Outcome {
+    pub score: i64,
+    pub error: Option<String>,
+    pub elapsed: f64,
+}
+
+// 任意のソルバーを子プロセスとして起動し、標準入出力でジャッジとして対話する
+// gamesはゲームのファイルかseed、a-bでseedの範囲
+// transcriptを指定すると、ゲームごとにソルバーの出力を<dir>/<name>.txt、標準エラーを<dir>/<name>.errに保存する
+// 保存した出力はcheckでそのまま検証できる
+// usage: harness <games> [tl=<sec>] [jobs=<n>] [transcript=<dir>] -- <command> [args...]
+pub fn run(args: &[String]) {
+    let split = args
+        .iter()
+        .position(|arg| arg == "--")
+        .expect("missing `--` before the solver command");
+    let (options, command) = (&args[..split], &args[split + 1..]);
+    if command.is_empty() {
+        panic!("missing solver command");
+    }
+    let mut time_limit = 2.0;
+    let mut jobs = 1;
+    let mut transcript: Option<String> = None;
+    let mut sources: Vec<String> = vec![];
+    for option in options {
+        match option.split_once('=') {
+            Some(("tl", value)) => time_limit = value.parse().unwrap(),
+            Some(("jobs", value)) => jobs = value.parse().unwrap(),
+            Some(("transcript", value)) => transcript = Some(value.to_string()),
+            _ => match option.split_once('-').map(|(a, b)| (a.parse(), b.parse())) {
+                Some((Ok(a), Ok(b))) => sources.extend((a..=b).map(|seed: u64| seed.to_string())),
+                _ => sources.push(option.clone()),
+            },
+        }
+    }
+    if let Some(dir) = &transcript {
+        std::fs::create_dir_all(dir).unwrap();
+    }
+
+    let outcomes: Vec<Outcome> = std::thread::scope(|s| {
+        let chunks: Vec<Vec<(usize, &String)>> = (0..jobs.max(1))
+            .map(|j| {
+                sources
+                    .iter()
+                    .enumerate()
+                    .skip(j)
+                    .step_by(jobs.max(1))
+                    .collect()
+            })
+            .collect();
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                let (command, transcript) = (command, &transcript);
+                s.spawn(move || {
+                    chunk
+                        .into_iter()
+                        .map(|(i, source)| {
+                            let game = Game::from_source(source).unwrap();
+                            let name = std::path::Path::new(source)
+                                .file_stem()
+                                .unwrap()
+                                .to_string_lossy()
+                                .to_string();
+                            let paths = transcript.as_ref().map(|dir| {
+                                (format!("{dir}/{name}.txt"), format!("{dir}/{name}.err"))
+                            });
+                            (i, play(&game, command, time_limit, paths))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut outcomes: Vec<(usize, Outcome)> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        outcomes.sort_by_key(|(i, _)| *i);
+        outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+    });
+
+    for (source, outcome) in sources.iter().zip(outcomes.iter()) {
+        match &outcome.error {
+            None => println!(
+                "{source}: score {} ({:.3}s)",
+                outcome.score, outcome.elapsed
+            ),
+            Some(e) => println!("{source}: error {e} ({:.3}s)", outcome.elapsed),
+        }
+    }
+    let n = outcomes.len() as f64;
+    println!(
+        "mean log score: {:.4}, errors: {}, max time: {:.3}s",
+        outcomes
+            .iter()
+            .map(|outcome| (outcome.score.max(1) as f64).ln())
+            .sum::<f64>()
+            / n,
+        outcomes
+            .iter()
+            .filter(|outcome| outcome.error.is_some())
+            .count(),
+        outcomes
+            .iter()
+            .fold(0., |a: f64, outcome| a.max(outcome.elapsed)),
+    );
+}
+
+// pathsは(出力の保存先, 標準エラーの保存先)
+pub fn play(
+    game: &Game,
+    command: &[String],
+    time_limit: f64,
+    paths: Option<(String, String)>,
+) -> Outcome {
+    let start = Instant::now();
+    let stderr = match &paths {
+        Some((_, err)) => Stdio::from(std::fs::File::create(err).unwrap()),
+        None => Stdio::null(),
+    };
+    let mut child = match Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(stderr)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            return Outcome {
+                score: 0,
+                error: Some(format!("failed to start `{}`: {e}", command[0])),
+                elapsed: 0.,
+            }
+        }
+    };
+
+    // 時間制限付きで読むために、出力は別スレッドで1行ずつ送る
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            match line {
+                Ok(line) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+
+    let mut transcript = String::new();
+    let deadline = start + Duration::from_secs_f64(time_limit);
+    let mut stdin = child.stdin.take().unwrap();
+    let result = (|| -> Result<i64, String> {
+        let mut judge = Judge::new(game);
+        let mut send = |text: String| {
+            stdin
+                .write_all(text.as_bytes())
+                .and_then(|_| stdin.flush())
+                .map_err(|_| "the solver closed its input".to_string())
+        };
+        let mut receive = |expected: usize| -> Result<Vec<usize>, String> {
+            loop {
+                let remain = deadline.saturating_duration_since(Instant::now());
+                let line = match receiver.recv_timeout(remain) {
+                    Ok(line) => line,
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        return Err("time limit exceeded".to_string())
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        return Err("the solver exited before the game ended".to_string())
+                    }
+                };
+                transcript += &line;
+                transcript += "\n";
+                if line.trim_start().starts_with('#') || line.trim().is_empty() {
+                    continue;
+                }
+                let values = line
+                    .split_whitespace()
+                    .map(|x| x.parse::<usize>())
+                    .collect::<Result<Vec<usize>, _>>()
+                    .map_err(|_| format!("invalid output: `{line}`"))?;
+                if values.len() != expected {
+                    return Err(format!("expected {expected} values, found `{line}`"));
+                }
+                return Ok(values);
+            }
+        };
+
+        let input = &game.input;
+        let mut text = format!("{} {} {} {}\n", input.n, input.m, input.k, input.t);
+        for card in judge.cards.iter() {
+            text += &format!("{} {}\n", card.to_t(), card_w(card));
+        }
+        for project in judge.projects.iter() {
+            text += &format!("{} {}\n", project.h, project.v);
+        }
+        send(text)?;
+
+        while !judge.is_finished() {
+            let t = judge.t;
+            let cm = receive(2)?;
+            judge
+                .use_card(cm[0], cm[1])
+                .map_err(|e| format!("turn {t}: {e}"))?;
+            let mut text = String::new();
+            for project in judge.projects.iter() {
+                text += &format!("{} {}\n", project.h, project.v);
+            }
+            text += &format!("{}\n", judge.money);
+            for offer in judge.offers.iter() {
+                text += &format!(
+                    "{} {} {}\n",
+                    offer.card.to_t(),
+                    card_w(&offer.card),
+                    offer.price
+                );
+            }
+            send(text)?;
+            let r = receive(1)?;
+            judge.refill(r[0]).map_err(|e| format!("turn {t}: {e}"))?;
+        }
+        Ok(judge.money)
+    })();
+    let elapsed = start.elapsed().as_secs_f64();
+
+    // ゲームが終わった後、入力を閉じてもEXIT_GRACE以内に終了しなければ止めてエラーにする
+    let result = match result {
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(e)
+        }
+        Ok(score) => {
+            drop(stdin);
+            let exit_deadline = Instant::now() + EXIT_GRACE;
+            loop {
+                match child.try_wait() {
+                    Ok(Some(_)) => break Ok(score),
+                    Ok(None) if Instant::now() < exit_deadline => {
+                        std::thread::sleep(Duration::from_millis(10))
+                    }
+                    _ => {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        break Err("the solver did not exit after the game ended".to_string());
+                    }
+                }
+            }
+        }
+    };
+    if let Some((out, _)) = &paths {
+        std::fs::write(out, &transcript).unwrap();
+    }
+    match result {
+        Ok(score) => Outcome {
+            score,
+            error: None,
+            elapsed,
+        },
+        Err(e) => Outcome {
+            score: 0,
+            error: Some(e),
+            elapsed,
+        },
+    }
+}
@@ -1,12 +1,14 @@
 use crate::def::*;
+use crate::trace::*;
 use std::io::{Stdin, Write};
 
 use proconio::*;
 
 pub trait Interactor {
-    fn output_c(&mut self, c: usize, m: usize);
-    fn output_r(&mut self, r: usize);
-    fn read_status(&mut self, input: &Input) -> (Vec<Project>, i64, Vec<(Card, i64)>);
+    fn output(&mut self, action: Action);
+    fn read_status(&mut self, input: &Input) -> (Vec<Project>, i64, Vec<Offer>);
+    // #で始まる行として出力する、ジャッジには無視される
+    fn comment(&mut self, message: &str);
 }
 
 pub struct IOInteractor {
@@ -41,6 +43,7 @@ impl IOInteractor {
         (
             Input { n, m, k, t },
             State {
+                turn: 0,
                 last_invest_round: 0,
                 invest_level: 0,
                 score: 0,
@@ -56,29 +59,99 @@ impl IOInteractor {
 }
 
 impl Interactor for IOInteractor {
-    fn output_c(&mut self, c: usize, m: usize) {
-        println!("{} {}", c, m);
+    fn output(&mut self, action: Action) {
+        println!("{}", action);
         self.flush();
     }
 
-    fn output_r(&mut self, r: usize) {
-        println!("{}", r);
+    fn comment(&mut self, message: &str) {
+        println!("# {}", message);
         self.flush();
     }
 
-    fn read_status(&mut self, input: &Input) -> (Vec<Project>, i64, Vec<(Card, i64)>) {
+    fn read_status(&mut self, input: &Input) -> (Vec<Project>, i64, Vec<Offer>) {
         input! {
             from &mut self.source,
             hv: [(i64, i64); input.m],
             money: i64,
             twp: [(usize, i64, i64); input.k],
         }
-        let projects = hv.iter().copied().map(|(h, v)| Project { h, v }).collect();
-        let cards: Vec<(Card, i64)> = twp
+        let projects: Vec<Project> = hv.iter().copied().map(|(h, v)| Project { h, v }).collect();
+        let offers = twp
             .iter()
             .copied()
-            .map(|(t, w, p)| (Card::from_tw(t, w), p))
+            .map(|(t, w, p)| Offer {
+                card: Card::from_tw(t, w),
+                price: p,
+            })
             .collect();
-        (projects, money, cards)
+        (projects, money, offers)
+    }
+}
+
+// 内側のInteractorとのやり取りをファイルにも書き出す
+// 出力はそのままの行、読んだ内容は # < で始まるコメント行にする
+// checkにログだけを渡すと、読んだ内容から入力を復元して出力を検証する（Game::parse_log）
+// 見えていない乱数列は復元できないので、whatifには使えない
+pub struct LoggingInteractor<I: Interactor> {
+    inner: I,
+    file: std::io::BufWriter<std::fs::File>,
+}
+
+impl<I: Interactor> LoggingInteractor<I> {
+    pub fn new(inner: I, path: &str) -> LoggingInteractor<I> {
+        LoggingInteractor {
+            inner,
+            file: std::io::BufWriter::new(std::fs::File::create(path).unwrap()),
+        }
+    }
+
+    // 最初の入力はInteractorを通らないので、読んだ後に渡す
+    pub fn log_input(&mut self, input: &Input, state: &State) {
+        writeln!(
+            self.file,
+            "# < {} {} {} {} | {} | {}",
+            input.n,
+            input.m,
+            input.k,
+            input.t,
+            format_cards(&state.cards),
+            format_projects(&state.projects)
+        )
+        .unwrap();
+    }
+}
+
+impl<I: Interactor> Interactor for LoggingInteractor<I> {
+    fn output(&mut self, action: Action) {
+        writeln!(self.file, "{}", action).unwrap();
+        self.inner.output(action);
+    }
+
+    fn read_status(&mut self, input: &Input) -> (Vec<Project>, i64, Vec<Offer>) {
+        let (projects, money, offers) = self.inner.read_status(input);
+        writeln!(
+            self.file,
+            "# < {} | {} | {}",
+            format_projects(&projects),
+            money,
+            format_offers(&offers)
+        )
+        .unwrap();
+        (projects, money, offers)
+    }
+
+    fn comment(&mut self, message: &str) {
+        writeln!(self.file, "# {}", message).unwrap();
+        self.inner.comment(message);
+    }
+}
+
+// BufWriterはdropでのflushの失敗を捨てるので、ここで報告する
+impl<I: Interactor> Drop for LoggingInteractor<I> {
+    fn drop(&mut self) {
+        if let Err(e) = self.file.flush() {
+            eprintln!("failed to write the log: {e}");
+        }
     }
 }
@@ -0,0 +1,263 @@
+// スナップショットなどを読むための最小限のJSON
+// 数値はf64で持つ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            s: text.as_bytes(),
+            i: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.i != parser.s.len() {
+            return Err(format!("unexpected trailing data at {}", parser.i));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(x) if x.fract() == 0. => Some(*x as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    // 整数の配列 [1, 2, 3]
+    pub fn as_i64_vec(&self) -> Option<Vec<i64>> {
+        self.as_array()?.iter().map(|x| x.as_i64()).collect()
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.i < self.s.len() && self.s[self.i].is_ascii_whitespace() {
+            self.i += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.s.get(self.i) == Some(&c) {
+            self.i += 1;
+            Ok(())
+        } else {
+            Err(format!("expected `{}` at {}", c as char, self.i))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.s.get(self.i) {
+            None => Err("unexpected end of json".to_string()),
+            Some(b'{') => {
+                self.i += 1;
+                let mut entries = vec![];
+                self.skip_whitespace();
+                if self.s.get(self.i) == Some(&b'}') {
+                    self.i += 1;
+                    return Ok(Json::Object(entries));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    entries.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.s.get(self.i) {
+                        Some(b',') => self.i += 1,
+                        Some(b'}') => {
+                            self.i += 1;
+                            return Ok(Json::Object(entries));
+                        }
+                        _ => return Err(format!("expected `,` or `}}` at {}", self.i)),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.i += 1;
+                let mut values = vec![];
+                self.skip_whitespace();
+                if self.s.get(self.i) == Some(&b']') {
+                    self.i += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.s.get(self.i) {
+                        Some(b',') => self.i += 1,
+                        Some(b']') => {
+                            self.i += 1;
+                            return Ok(Json::Array(values));
+                        }
+                        _ => return Err(format!("expected `,` or `]` at {}", self.i)),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(_) => {
+                let start = self.i;
+                while self.i < self.s.len()
+                    && !matches!(self.s[self.i], b',' | b']' | b'}')
+                    && !self.s[self.i].is_ascii_whitespace()
+                {
+                    self.i += 1;
+                }
+                let token = std::str::from_utf8(&self.s[start..self.i]).unwrap();
+                match token {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => token
+                        .parse::<f64>()
+                        .map(Json::Number)
+                        .map_err(|_| format!("invalid token `{token}` at {start}")),
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.s.get(self.i) != Some(&b'"') {
+            return Err(format!("expected string at {}", self.i));
+        }
+        self.i += 1;
+        let mut bytes = vec![];
+        loop {
+            match self.s.get(self.i) {
+                None => return Err("unterminated string".to_string()),
+                Some(b'"') => {
+                    self.i += 1;
+                    return String::from_utf8(bytes).map_err(|e| e.to_string());
+                }
+                Some(b'\\') => {
+                    let c = *self.s.get(self.i + 1).ok_or("unterminated string")?;
+                    self.i += 2;
+                    let escaped = match c {
+                        b'"' | b'\\' | b'/' => c as char,
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => {
+                            return Err(format!(
+                                "invalid escape `\\{}` at {}",
+                                c as char,
+                                self.i - 2
+                            ))
+                        }
+                    };
+                    bytes.extend(escaped.to_string().as_bytes());
+                }
+                Some(&c) if c < 0x20 => {
+                    return Err(format!("unescaped control character at {}", self.i));
+                }
+                Some(&c) => {
+                    bytes.push(c);
+                    self.i += 1;
+                }
+            }
+        }
+    }
+
+    // \u の後の4桁、サロゲートペアは続く \uXXXX と合わせる
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.s.get(self.i..self.i + 2) != Some(b"\\u") {
+                return Err(format!("unpaired surrogate at {}", self.i));
+            }
+            self.i += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(format!("unpaired surrogate at {}", self.i));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| format!("invalid code point at {}", self.i))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .s
+            .get(self.i..self.i + 4)
+            .filter(|d| d.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| format!("invalid \\u escape at {}", self.i))?;
+        self.i += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_string_escapes() {
+        let json =
+            Json::parse(r#"["a\"b\\c\/d", "\b\f\n\r\t", "\u00e9\u3042", "\ud83d\ude00"]"#).unwrap();
+        let strings: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x.as_str().unwrap())
+            .collect();
+        assert_eq!(strings, vec!["a\"b\\c/d", "\u{8}\u{c}\n\r\t", "éあ", "😀"]);
+    }
+
+    #[test]
+    fn reject_invalid_strings() {
+        for text in [
+            r#""\q""#,
+            r#""\u12""#,
+            r#""\u12g4""#,
+            r#""\ud83d""#,
+            r#""\ud83dA""#,
+            "\"a\tb\"",
+            r#""abc"#,
+        ] {
+            assert!(Json::parse(text).is_err(), "{text}");
+        }
+    }
+}
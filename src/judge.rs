@@ -0,0 +1,270 @@
+use crate::def::*;
+use crate::simulator::*;
+
+// 公式のルールでゲームを進める
+// MockInteractorと違って、ルールに反する行動はエラーにする
+pub struct Judge<'a> {
+    game: &'a Game,
+    pub t: usize,
+    pub money: i64,
+    pub invest_level: usize,
+    pub cards: Vec<Card>,
+    pub projects: Vec<Project>,
+    pub offers: Vec<Offer>,
+    used_card: Option<usize>,
+    next_project: usize,
+}
+
+impl Judge<'_> {
+    pub fn new(game: &Game) -> Judge<'_> {
+        Judge {
+            game,
+            t: 0,
+            money: 0,
+            invest_level: 0,
+            cards: game.cards.clone(),
+            projects: game.projects.clone(),
+            offers: vec![],
+            used_card: None,
+            next_project: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.t >= self.game.input.t
+    }
+
+    // カードを使って、このターンの補充の候補を作る
+    pub fn use_card(&mut self, c: usize, m: usize) -> Result<(), String> {
+        let input = &self.game.input;
+        if self.is_finished() {
+            return Err("the game is already over".to_string());
+        }
+        if self.used_card.is_some() {
+            return Err("expected a refill, found another card".to_string());
+        }
+        let action = Action::parse_use(c, m, &self.cards)?;
+        match action {
+            Action::UseSingle { project, .. } if project >= input.m => {
+                return Err(format!("project {m} is out of range (0..{})", input.m));
+            }
+            Action::Invest { .. } if self.invest_level >= MAX_INVEST_LEVEL => {
+                return Err(format!("invest beyond level {MAX_INVEST_LEVEL}"));
+            }
+            _ => {}
+        }
+
+        match self.cards[c] {
+            Card::WorkSingle(w) => self.work(m, w),
+            Card::WorkAll(w) => {
+                for i in 0..input.m {
+                    self.work(i, w);
+                }
+            }
+            Card::CancelSingle => self.replace_project(m),
+            Card::CancelAll => {
+                for i in 0..input.m {
+                    self.replace_project(i);
+                }
+            }
+            Card::Invest => self.invest_level += 1,
+            Card::None => unreachable!(),
+        }
+        self.cards[c] = Card::None;
+        self.used_card = Some(c);
+
+        let scale = 1 << self.invest_level;
+        self.offers = self.game.new_cards[self.t]
+            .iter()
+            .map(|offer| {
+                let card = match offer.card {
+                    Card::WorkSingle(w) => Card::WorkSingle(w * scale),
+                    Card::WorkAll(w) => Card::WorkAll(w * scale),
+                    card => card,
+                };
+                Offer {
+                    card,
+                    price: offer.price * scale,
+                }
+            })
+            .collect();
+        Ok(())
+    }
+
+    pub fn refill(&mut self, r: usize) -> Result<(), String> {
+        let c = self
+            .used_card
+            .ok_or("expected a card to use, found a refill")?;
+        if r >= self.offers.len() {
+            return Err(format!(
+                "refill {r} is out of range (0..{})",
+                self.offers.len()
+            ));
+        }
+        let Offer { card, price: p } = self.offers[r];
+        if p > self.money {
+            return Err(format!(
+                "refill {r} costs {p} but only {} is available",
+                self.money
+            ));
+        }
+        self.money -= p;
+        self.cards[c] = card;
+        self.used_card = None;
+        self.t += 1;
+        Ok(())
+    }
+
+    fn work(&mut self, m: usize, w: i64) {
+        self.projects[m].h -= w;
+        if self.projects[m].h <= 0 {
+            self.money += self.projects[m].v;
+            self.replace_project(m);
+        }
+    }
+
+    fn replace_project(&mut self, m: usize) {
+        let project = self.game.new_projects[self.next_project];
+        self.next_project += 1;
+        let scale = 1 << self.invest_level;
+        self.projects[m] = Project {
+            h: project.h * scale,
+            v: project.v * scale,
+        };
+    }
+}
+
+// 出力（c m と r を交互に並べたもの、#で始まる行はコメント）をゲームの入力と乱数列の上で再生し、スコアか最初の不正な行動を表示する
+// 不正な場合は終了コード1で終わる
+// AHC029_LOGのログを渡した場合は、ログ自身の出力をログから復元した入力で検証する
+// usage: check <game|seed> <output> | check <log>
+pub fn run(args: &[String]) {
+    let source = args.first().map(|s| s.as_str()).unwrap_or("0");
+    let log = std::fs::read_to_string(source)
+        .ok()
+        .filter(|text| text.starts_with("# <"));
+    let (game, output) = match log {
+        Some(text) => match Game::parse_log(&text) {
+            Ok(game) => (game, text),
+            Err(e) => {
+                println!("invalid log: {e}");
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let path = args.get(1).map(|s| s.as_str()).unwrap_or("out.txt");
+            (
+                Game::from_source(source).unwrap(),
+                std::fs::read_to_string(path).unwrap(),
+            )
+        }
+    };
+
+    match check(&game, &output) {
+        Ok(score) => println!("score: {score}"),
+        Err((t, e)) => {
+            println!("invalid at turn {t}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+// 不正な場合はターンと理由を返す
+pub fn check(game: &Game, output: &str) -> Result<i64, (usize, String)> {
+    let mut judge = Judge::new(game);
+    let mut tokens = output
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split_whitespace());
+    while !judge.is_finished() {
+        let t = judge.t;
+        let mut next = |name: &str| -> Result<usize, (usize, String)> {
+            let token = tokens
+                .next()
+                .ok_or_else(|| (t, format!("unexpected end of output while reading {name}")))?;
+            token
+                .parse::<usize>()
+                .map_err(|_| (t, format!("invalid {name}: `{token}`")))
+        };
+        let (c, m) = (next("c")?, next("m")?);
+        judge.use_card(c, m).map_err(|e| (t, e))?;
+        let r = next("r")?;
+        judge.refill(r).map_err(|e| (t, e))?;
+    }
+    if let Some(token) = tokens.next() {
+        return Err((judge.t, format!("unexpected trailing output: `{token}`")));
+    }
+    Ok(judge.money)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> Game {
+        Game::parse(
+            "2 2 2 2
+0 1
+0 1
+1 5
+3 7
+2 2
+4 4
+8 8
+16 16
+0 1 0
+4 0 300
+0 1 0
+2 0 5
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn play_a_game() {
+        let game = game();
+        let mut judge = Judge::new(&game);
+        judge.use_card(0, 0).unwrap();
+        // 完了したプロジェクトは乱数列の先頭と入れ替わる
+        assert_eq!(judge.money, 5);
+        assert_eq!(judge.projects[0], Project { h: 2, v: 2 });
+        assert_eq!(judge.offers, game.new_cards[0]);
+        judge.refill(0).unwrap();
+        assert_eq!(judge.cards, vec![Card::WorkSingle(1); 2]);
+        judge.use_card(1, 1).unwrap();
+        judge.refill(1).unwrap();
+        assert_eq!(judge.money, 0);
+        assert_eq!(judge.cards[1], Card::CancelSingle);
+        assert!(judge.is_finished());
+    }
+
+    #[test]
+    fn reject_invalid_actions() {
+        let game = game();
+        let mut judge = Judge::new(&game);
+        assert!(judge.refill(0).is_err());
+        assert!(judge.use_card(2, 0).is_err());
+        assert!(judge.use_card(0, 2).is_err());
+        judge.use_card(0, 1).unwrap();
+        assert!(judge.use_card(1, 0).is_err());
+        assert!(judge.refill(2).is_err());
+        // 所持金0で価格300の増資は買えない
+        assert!(judge.refill(1).is_err());
+        judge.refill(0).unwrap();
+        judge.use_card(0, 0).unwrap();
+        judge.refill(0).unwrap();
+        assert!(judge.use_card(0, 0).is_err());
+    }
+
+    #[test]
+    fn check_output() {
+        let game = game();
+        assert_eq!(check(&game, "# comment\n0 0\n0\n1 1\n1\n"), Ok(0));
+        assert_eq!(check(&game, "0 0\n0\n1 1\n0\n"), Ok(5));
+        assert_eq!(check(&game, "0 0\n1\n").unwrap_err().0, 0);
+        assert_eq!(check(&game, "0 0\n0\n1 1").unwrap_err().0, 1);
+        assert_eq!(check(&game, "0 0\n0\n1 x\n0\n").unwrap_err().0, 1);
+        assert_eq!(check(&game, "0 0\n0\n1 1\n0\n0 0\n").unwrap_err().0, 2);
+    }
+}
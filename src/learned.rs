@@ -0,0 +1,198 @@
+use crate::card_valuer::*;
+use crate::def::*;
+
+pub const FEATURE_COUNT: usize = 13;
+
+// 提出用に埋め込む重み
+// 初期値は手調整の評価値（特徴量0）をそのまま返す線形モデル
+pub const EMBEDDED_WEIGHTS: &str = "
+layer 13 1
+1 0 0 0 0 0 0 0 0 0 0 0 0 0
+";
+
+// valueは手調整の評価値で、-INFになるカード（使えない・買えない）は呼び出し側で除く
+// 金額は増資レベルで正規化して、増資前後で同じ重みを使えるようにする
+pub fn features(
+    valuer: &CardValuer,
+    state: &State,
+    card: &Card,
+    p: i64,
+    t: usize,
+    value: f64,
+) -> [f64; FEATURE_COUNT] {
+    let scale = (1_i64 << state.invest_level) as f64;
+    let w = match card {
+        Card::WorkSingle(w) | Card::WorkAll(w) => *w,
+        _ => 0,
+    };
+    let mean_h =
+        state.projects.iter().map(|proj| proj.h).sum::<i64>() as f64 / state.projects.len() as f64;
+    let mean_v =
+        state.projects.iter().map(|proj| proj.v).sum::<i64>() as f64 / state.projects.len() as f64;
+
+    [
+        value / scale,
+        w as f64 / scale,
+        p as f64 / scale,
+        valuer.overflow(card, p) / scale,
+        (card.to_t() == 0) as usize as f64,
+        (card.to_t() == 1) as usize as f64,
+        (card.to_t() == 2) as usize as f64,
+        (card.to_t() == 3) as usize as f64,
+        t as f64 / 1000.,
+        state.invest_level as f64 / MAX_INVEST_LEVEL as f64,
+        (state.score as f64 / scale).ln_1p(),
+        mean_h / scale / 100.,
+        mean_v / scale / 100.,
+    ]
+}
+
+// 全結合層を重ねたモデル、中間層の活性化関数はReLU
+// 1層だけなら線形モデルになる
+//
+// 重みファイルの形式（#以降はコメント）:
+// layer <入力次元> <出力次元>
+// 出力次元の行数だけ、入力次元個の重みとバイアスを並べる
+#[derive(Debug, Clone)]
+pub struct LearnedModel {
+    layers: Vec<Layer>,
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    weights: Vec<Vec<f64>>,
+    bias: Vec<f64>,
+}
+
+impl LearnedModel {
+    pub fn load(path: &str) -> Result<LearnedModel, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        LearnedModel::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<LearnedModel, String> {
+        let mut tokens = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap())
+            .flat_map(|line| line.split_whitespace());
+        let mut layers: Vec<Layer> = vec![];
+
+        while let Some(token) = tokens.next() {
+            if token != "layer" {
+                return Err(format!("expected `layer`, found `{token}`"));
+            }
+            let input_dim = next_dim(&mut tokens, "input dim")?;
+            let output_dim = next_dim(&mut tokens, "output dim")?;
+            let expected_dim = layers
+                .last()
+                .map(|layer| layer.bias.len())
+                .unwrap_or(FEATURE_COUNT);
+            if input_dim != expected_dim {
+                return Err(format!(
+                    "layer {} expects input dim {expected_dim}, found {input_dim}",
+                    layers.len()
+                ));
+            }
+
+            let mut weights = vec![vec![0.; input_dim]; output_dim];
+            let mut bias = vec![0.; output_dim];
+            for (row, b) in weights.iter_mut().zip(bias.iter_mut()) {
+                for x in row.iter_mut() {
+                    *x = next_number(&mut tokens, "weight")?;
+                }
+                *b = next_number(&mut tokens, "bias")?;
+            }
+            layers.push(Layer { weights, bias });
+        }
+
+        match layers.last() {
+            None => Err("no layers".to_string()),
+            Some(layer) if layer.bias.len() != 1 => Err(format!(
+                "last layer must have output dim 1, found {}",
+                layer.bias.len()
+            )),
+            _ => Ok(LearnedModel { layers }),
+        }
+    }
+
+    pub fn predict(&self, features: &[f64]) -> f64 {
+        let mut x = features.to_vec();
+        for (i, layer) in self.layers.iter().enumerate() {
+            x = layer
+                .weights
+                .iter()
+                .zip(layer.bias.iter())
+                .map(|(row, b)| row.iter().zip(x.iter()).map(|(w, x)| w * x).sum::<f64>() + b)
+                .collect();
+            if i + 1 < self.layers.len() {
+                for x in x.iter_mut() {
+                    *x = x.max(0.);
+                }
+            }
+        }
+        x[0]
+    }
+}
+
+fn next_token<'a>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    name: &str,
+) -> Result<&'a str, String> {
+    tokens
+        .next()
+        .ok_or_else(|| format!("unexpected end of weights while reading {name}"))
+}
+
+fn next_number<'a>(tokens: &mut impl Iterator<Item = &'a str>, name: &str) -> Result<f64, String> {
+    let token = next_token(tokens, name)?;
+    token
+        .parse::<f64>()
+        .map_err(|_| format!("invalid {name}: `{token}`"))
+}
+
+// 層の次元は正の整数
+fn next_dim<'a>(tokens: &mut impl Iterator<Item = &'a str>, name: &str) -> Result<usize, String> {
+    let token = next_token(tokens, name)?;
+    match token.parse::<usize>() {
+        Ok(dim) if dim > 0 => Ok(dim),
+        _ => Err(format!("invalid {name}: `{token}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 特徴量の和にReLUをかけて2倍し、1を足すモデル
+    fn two_layers() -> String {
+        let ones = vec!["1"; FEATURE_COUNT].join(" ");
+        format!("layer {FEATURE_COUNT} 1 # hidden\n{ones} 0\nlayer 1 1\n2 1\n")
+    }
+
+    #[test]
+    fn parse_and_predict() {
+        let model = LearnedModel::parse(&two_layers()).unwrap();
+        let mut features = [0.5; FEATURE_COUNT];
+        assert_eq!(model.predict(&features), 1. + FEATURE_COUNT as f64);
+        features[0] = -100.;
+        assert_eq!(model.predict(&features), 1.);
+    }
+
+    #[test]
+    fn reject_invalid_weights() {
+        let text = two_layers();
+        for bad in [
+            String::new(),
+            text.replace("layer 1 1\n", "layer 2 1\n"),
+            text.replace("layer 1 1\n2 1\n", "layer 1 2\n2 1\n2 1\n"),
+            text.replace("layer 1 1\n", "layer 1.0 1\n"),
+            text.replace("layer 1 1\n", "layer 0 1\n"),
+            text.replace("2 1\n", "2\n"),
+            text.replace("2 1\n", "2 x\n"),
+            text.replace("layer 1 1", "lyr 1 1"),
+            format!("layer {} 1\n", FEATURE_COUNT + 1),
+        ] {
+            assert!(LearnedModel::parse(&bad).is_err(), "{bad}");
+        }
+    }
+}
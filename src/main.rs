@@ -1,58 +1,151 @@
+mod card_valuer;
+mod dataset;
 mod def;
+mod expectimax;
+mod explain;
+mod forecast;
+mod hand;
+mod harness;
 mod interactor;
+mod json;
+mod judge;
+mod learned;
+mod param_table;
+mod planner;
+mod project_model;
 mod simulator;
+mod snapshot;
 mod solver;
+mod stockpile;
+mod trace;
+mod tuner;
 mod util;
+mod vis;
+mod whatif;
 
-use crate::def::*;
+use crate::expectimax::*;
 use crate::interactor::*;
+use crate::learned::*;
+use crate::param_table::*;
+use crate::project_model::*;
 use crate::solver::*;
 use crate::util::*;
 
-fn load_params() -> Param {
-    if false {
-        use std::env;
-        let args: Vec<String> = env::args().collect();
-        Param {
-            overflow_alpha_refill: args[1].parse().unwrap(),
-            overflow_alpha: args[2].parse().unwrap(),
-            cancel_alpha_refill: args[3].parse().unwrap(),
-            cancel_alpha: args[4].parse().unwrap(),
-            invest_limit: args[5].parse().unwrap(),
-            cancel_limit: args[6].parse().unwrap(),
-            invest_cost: args[7].parse().unwrap(),
-            overflow_alpha_all_refill: args[8].parse().unwrap(),
-            overflow_alpha_all: args[9].parse().unwrap(),
-            cancel_alpha_all_refill: args[10].parse().unwrap(),
-            cancel_alpha_all: args[11].parse().unwrap(),
-            work_single_beta: args[12].parse().unwrap(),
-        }
-    } else {
-        Param {
-            cancel_alpha: 3.9025157664873658,
-            cancel_alpha_all: 5.034673414949709,
-            cancel_alpha_all_refill: 1.070299470579473,
-            cancel_alpha_refill: 1.0009104398160726,
-            cancel_limit: 979,
-            invest_cost: 510,
-            invest_limit: 863,
-            overflow_alpha: 2.016646721749814,
-            overflow_alpha_all: 2.7694849713061416,
-            overflow_alpha_all_refill: 0.5531191229327318,
-            overflow_alpha_refill: 0.25378716369770815,
-            work_single_beta: 2.898910615818517,
-        }
+// 入力サイズごとのパラメータ、ローカルでは AHC029_PARAMS=<ファイル> で差し替える
+// [default]は全てのパラメータでのtune（seed 1000-1099）の結果に、手で調整した値を混ぜたもの
+// 今のパラメータの組で取り直したtune（seed 1000-1199、40世代）はseed 100-699の平均で-0.014だったので採らない
+// 区間は使っていない、n=2-3とn=4-7を別々にtuneした区間（seed 1000以降の各100ゲーム、30世代）はseed 100-699の平均で-0.066だった
+const PARAM_TABLE: &str = "
+[default]
+overflow_alpha = 4.0
+overflow_alpha_refill = 0.360035112177377 0.23348399941670664 0.22915755808949798
+overflow_alpha_all_refill = 0.4080693413057788 0.5660957262002836 0.5666113183086174
+invest_limit = 869
+cancel_limit = 991
+invest_cost = 738
+work_single_beta = 2.685067327232988 3.6512143894436497 1.693614433784108
+hand_weight = 0.7603475691096448 0.03915578719753145 0.39116491676083576
+hand_decay = 0.9594886107240115
+stockpile_h = 1000
+plan_horizon = 0
+";
+
+fn load_param_table() -> ParamTable {
+    match std::env::var("AHC029_PARAMS") {
+        Ok(path) => ParamTable::load(&path).unwrap(),
+        Err(_) => ParamTable::parse(PARAM_TABLE).unwrap(),
+    }
+}
+
+// 提出時に学習済みの評価関数を使う場合はtrueにする
+const USE_EMBEDDED_WEIGHTS: bool = false;
+
+// ローカルでは AHC029_WEIGHTS=<重みファイル> で学習済みの評価関数に切り替える
+fn load_evaluator() -> Evaluator {
+    if USE_EMBEDDED_WEIGHTS {
+        return Evaluator::Learned(LearnedModel::parse(EMBEDDED_WEIGHTS).unwrap());
+    }
+    match std::env::var("AHC029_WEIGHTS") {
+        Ok(path) => Evaluator::Learned(LearnedModel::load(&path).unwrap()),
+        Err(_) => Evaluator::Param,
+    }
+}
+
+// AHC029_VERBOSE でコメント行の詳しさを指定する（提出時は0）
+fn load_verbosity() -> usize {
+    match std::env::var("AHC029_VERBOSE") {
+        Ok(level) => level.parse().unwrap(),
+        Err(_) => 0,
+    }
+}
+
+// AHC029_POLICY で方策を指定する（greedy か expectimax[:depth[:width[:samples]]]）
+fn load_policy() -> Policy {
+    match std::env::var("AHC029_POLICY") {
+        Ok(policy) => Policy::parse(&policy).unwrap(),
+        Err(_) => Policy::Greedy,
     }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() >= 2 && args[1] == "dataset" {
+        dataset::run(&args[2..], &load_param_table(), &load_evaluator());
+        return;
+    }
+    if args.len() >= 2 && args[1] == "harness" {
+        harness::run(&args[2..]);
+        return;
+    }
+    if args.len() >= 2 && args[1] == "tune" {
+        tuner::run(&args[2..], &load_param_table(), &load_evaluator());
+        return;
+    }
+    if args.len() >= 2 && args[1] == "check" {
+        judge::run(&args[2..]);
+        return;
+    }
+    if args.len() >= 2 && args[1] == "explain" {
+        explain::run(&args[2..], &load_param_table(), &load_evaluator());
+        return;
+    }
+    if args.len() >= 2 && args[1] == "snapshot" {
+        snapshot::run(&args[2..], &load_param_table(), &load_evaluator());
+        return;
+    }
+    if args.len() >= 2 && args[1] == "vis" {
+        vis::run(&args[2..], &load_param_table(), &load_evaluator());
+        return;
+    }
+    if args.len() >= 2 && args[1] == "whatif" {
+        whatif::run(&args[2..], &load_param_table(), &load_evaluator());
+        return;
+    }
+
     time::start_clock();
     let mut interactor = IOInteractor::new();
     let (input, state) = interactor.read_input();
-    let param = load_params();
-    let mut solver = Solver { state, param };
+    let param = load_param_table().lookup(&input);
+    let mut solver = Solver {
+        state,
+        param,
+        project_model: ProjectModel::new(),
+        evaluator: load_evaluator(),
+        verbosity: load_verbosity(),
+        policy: load_policy(),
+    };
 
-    solver.solve(&input, &mut interactor);
+    // AHC029_LOG=<ファイル> でジャッジとのやり取りを書き出す
+    match std::env::var("AHC029_LOG") {
+        Ok(path) => {
+            let mut interactor = LoggingInteractor::new(interactor, &path);
+            interactor.log_input(&input, &solver.state);
+            solver.solve(&input, &mut interactor);
+        }
+        Err(_) => {
+            solver.solve(&input, &mut interactor);
+        }
+    }
     eprintln!(
         "result: {{\"score\": {}, \"duration\": {:.4}, \"invest_level\": {}}}",
         solver.state.score,
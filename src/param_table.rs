@@ -0,0 +1,310 @@
+use crate::def::*;
+
+// N, M, K の範囲、*は全て
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bucket {
+    pub n: (usize, usize),
+    pub m: (usize, usize),
+    pub k: (usize, usize),
+}
+
+impl Bucket {
+    pub const ALL: Bucket = Bucket {
+        n: (2, 7),
+        m: (2, 8),
+        k: (2, 5),
+    };
+
+    // "n=2-3 m=* k=4" の形式、区切りは空白かカンマ
+    // 範囲はALLの範囲に収まり、下限が上限以下でなければならない
+    pub fn parse(s: &str) -> Result<Bucket, String> {
+        let mut bucket = Bucket::ALL;
+        for token in s.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() {
+                continue;
+            }
+            let (key, range) = token
+                .split_once('=')
+                .ok_or_else(|| format!("invalid bucket: `{token}`"))?;
+            let range = match range {
+                "*" => None,
+                _ => {
+                    let (lo, hi) = range.split_once('-').unwrap_or((range, range));
+                    let parse = |x: &str| {
+                        x.parse::<usize>()
+                            .map_err(|_| format!("invalid bucket range: `{token}`"))
+                    };
+                    Some((parse(lo)?, parse(hi)?))
+                }
+            };
+            let (field, all) = match key {
+                "n" => (&mut bucket.n, Bucket::ALL.n),
+                "m" => (&mut bucket.m, Bucket::ALL.m),
+                "k" => (&mut bucket.k, Bucket::ALL.k),
+                _ => return Err(format!("invalid bucket key: `{key}`")),
+            };
+            *field = match range {
+                Some((lo, hi)) if all.0 <= lo && lo <= hi && hi <= all.1 => (lo, hi),
+                Some(_) => {
+                    return Err(format!(
+                        "bucket range out of {}-{} or inverted: `{token}`",
+                        all.0, all.1
+                    ))
+                }
+                None => all,
+            };
+        }
+        Ok(bucket)
+    }
+
+    pub fn contains(&self, input: &Input) -> bool {
+        let within = |x: usize, (lo, hi): (usize, usize)| lo <= x && x <= hi;
+        within(input.n, self.n) && within(input.m, self.m) && within(input.k, self.k)
+    }
+}
+
+impl std::fmt::Display for Bucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let range = |(lo, hi): (usize, usize), all: (usize, usize)| {
+            if (lo, hi) == all {
+                "*".to_string()
+            } else if lo == hi {
+                lo.to_string()
+            } else {
+                format!("{lo}-{hi}")
+            }
+        };
+        write!(
+            f,
+            "n={} m={} k={}",
+            range(self.n, Bucket::ALL.n),
+            range(self.m, Bucket::ALL.m),
+            range(self.k, Bucket::ALL.k)
+        )
+    }
+}
+
+// 入力サイズごとのパラメータ
+// [default] で全てのパラメータを指定し、[n=.. m=.. k=..] の区間で上書きする
+// 複数の区間に当てはまる場合は後の区間が優先される
+//
+// スケジュールのパラメータは SCHEDULE_KNOTS の各ターンでの値を並べる（1つだけなら定数）
+// ノットは def.rs で t = 0, 500, 1000 に固定されていて、テーブルからは変えられない
+// 例えば "2.0 2.4 3.0" は t=0 で 2.0、t=500 で 2.4、t=1000 で 3.0 になり、間は線形補間する
+//
+// [default]
+// overflow_alpha = 2.4
+// ...
+// [n=2-3 m=* k=*]
+// overflow_alpha = 2.0 2.4 3.0
+#[derive(Debug, Clone)]
+pub struct ParamTable {
+    default: Param,
+    sections: Vec<(Bucket, Overrides)>,
+}
+
+// (Param::NAMESの添字, 値) の列
+type Overrides = Vec<(usize, Vec<f64>)>;
+
+impl ParamTable {
+    pub fn load(path: &str) -> Result<ParamTable, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        ParamTable::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<ParamTable, String> {
+        let mut default: Option<Vec<Option<Vec<f64>>>> = None;
+        let mut sections: Vec<(Bucket, Overrides)> = vec![];
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if header == "default" {
+                    default = Some(vec![None; Param::NAMES.len()]);
+                } else {
+                    if default.is_none() {
+                        return Err("[default] must come first".to_string());
+                    }
+                    sections.push((Bucket::parse(header)?, vec![]));
+                }
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("invalid line: `{line}`"))?;
+            let (name, value) = (name.trim(), value.trim());
+            let i = Param::NAMES
+                .iter()
+                .position(|&n| n == name)
+                .ok_or_else(|| format!("unknown param: `{name}`"))?;
+            let mut value = value
+                .split_whitespace()
+                .map(|x| x.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| format!("invalid value for {name}: `{value}`"))?;
+            if value.len() == 1 {
+                value = vec![value[0]; Param::DIMS[i]];
+            }
+            if value.len() != Param::DIMS[i] {
+                return Err(format!(
+                    "{name} expects 1 or {} values, found {}",
+                    Param::DIMS[i],
+                    value.len()
+                ));
+            }
+            match (sections.last_mut(), default.as_mut()) {
+                (Some((_, overrides)), _) => overrides.push((i, value)),
+                (None, Some(default)) => default[i] = Some(value),
+                (None, None) => return Err("[default] must come first".to_string()),
+            }
+        }
+
+        let default = default.ok_or_else(|| "missing [default]".to_string())?;
+        let mut x = vec![];
+        for (i, value) in default.into_iter().enumerate() {
+            x.extend(value.ok_or_else(|| format!("missing default: {}", Param::NAMES[i]))?);
+        }
+        Ok(ParamTable {
+            default: Param::from_vec(&x),
+            sections,
+        })
+    }
+
+    pub fn lookup(&self, input: &Input) -> Param {
+        self.lookup_bucket(|bucket| bucket.contains(input))
+    }
+
+    // bucket 全体に当てはまる区間だけを適用したパラメータ（チューニングの初期値）
+    pub fn lookup_for(&self, bucket: &Bucket) -> Param {
+        self.lookup_bucket(|b| {
+            b.n.0 <= bucket.n.0
+                && bucket.n.1 <= b.n.1
+                && b.m.0 <= bucket.m.0
+                && bucket.m.1 <= b.m.1
+                && b.k.0 <= bucket.k.0
+                && bucket.k.1 <= b.k.1
+        })
+    }
+
+    fn lookup_bucket<F: Fn(&Bucket) -> bool>(&self, matches: F) -> Param {
+        let mut x = self.default.to_vec();
+        for (bucket, overrides) in self.sections.iter() {
+            if matches(bucket) {
+                for (i, value) in overrides.iter() {
+                    let offset = Param::DIMS[..*i].iter().sum::<usize>();
+                    x[offset..offset + value.len()].copy_from_slice(value);
+                }
+            }
+        }
+        Param::from_vec(&x)
+    }
+}
+
+// テーブルにそのまま貼り付けられる形式
+pub fn format_section(header: &str, param: &Param) -> String {
+    let mut s = format!("[{header}]\n");
+    let x = param.to_vec();
+    let mut offset = 0;
+    for (name, dim) in Param::NAMES.iter().zip(Param::DIMS) {
+        let value = &x[offset..offset + dim];
+        offset += dim;
+        let value = if value.iter().all(|&v| v == value[0]) {
+            value[0].to_string()
+        } else {
+            value
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        s += &format!("{name} = {value}\n");
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(n: usize, m: usize, k: usize) -> Input {
+        Input { n, m, k, t: 1000 }
+    }
+
+    fn table() -> ParamTable {
+        ParamTable::parse(crate::PARAM_TABLE).unwrap()
+    }
+
+    #[test]
+    fn round_trip_section() {
+        let param = table().lookup(&input(2, 2, 2));
+        let text = format_section("default", &param);
+        let parsed = ParamTable::parse(&text).unwrap().lookup(&input(2, 2, 2));
+        assert_eq!(parsed.to_vec(), param.to_vec());
+        assert_eq!(format_section("default", &parsed), text);
+    }
+
+    #[test]
+    fn later_sections_override() {
+        let base = format_section("default", &table().lookup(&input(2, 2, 2)));
+        let text = base.clone()
+            + "[n=2-3]\ninvest_limit = 800\n[n=3 k=5]\ninvest_limit = 900\nhand_decay = 0.25\n";
+        let table = ParamTable::parse(&text).unwrap();
+        assert_eq!(table.lookup(&input(2, 8, 5)).invest_limit, 800);
+        assert_eq!(table.lookup(&input(3, 8, 5)).invest_limit, 900);
+        assert_eq!(table.lookup(&input(3, 8, 5)).hand_decay, 0.25);
+        let default = ParamTable::parse(&base).unwrap().lookup(&input(4, 8, 5));
+        assert_eq!(table.lookup(&input(4, 8, 5)).to_vec(), default.to_vec());
+        // 区間の一部にしか当てはまらない上書きはチューニングの初期値に使わない
+        let bucket = Bucket::parse("n=2-3").unwrap();
+        assert_eq!(table.lookup_for(&bucket).invest_limit, 800);
+    }
+
+    #[test]
+    fn reject_invalid_table() {
+        let base = format_section("default", &table().lookup(&input(2, 2, 2)));
+        for text in [
+            String::new(),
+            "[n=2]\ninvest_limit = 800\n".to_string(),
+            base.replace("invest_limit", "invest_limits"),
+            base.replace("hand_decay = ", "hand_decay = 0.1 "),
+            base.clone() + "invest_limit 800\n",
+            base.clone() + "invest_limit = x\n",
+            base.clone() + "[n=1-3]\n",
+            base.lines()
+                .filter(|line| !line.starts_with("invest_limit"))
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        ] {
+            assert!(ParamTable::parse(&text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn round_trip_bucket() {
+        for s in ["n=* m=* k=*", "n=2-3 m=* k=4", "n=7 m=2-8 k=2-3"] {
+            let bucket = Bucket::parse(s).unwrap();
+            assert_eq!(Bucket::parse(&bucket.to_string()).unwrap(), bucket);
+        }
+        assert_eq!(Bucket::parse("").unwrap(), Bucket::ALL);
+        assert_eq!(Bucket::parse("m=2-8").unwrap().to_string(), "n=* m=* k=*");
+        let bucket = Bucket::parse("n=2,3 k=4").unwrap_err();
+        assert!(bucket.contains("invalid bucket"), "{bucket}");
+        assert!(Bucket::parse("n=2-3,k=4")
+            .unwrap()
+            .contains(&input(3, 5, 4)));
+        assert!(!Bucket::parse("n=2-3,k=4")
+            .unwrap()
+            .contains(&input(4, 5, 4)));
+    }
+
+    #[test]
+    fn reject_invalid_bucket() {
+        for s in ["n=1-3", "n=5-3", "k=6", "t=2", "n", "n=a", "n=2-"] {
+            assert!(Bucket::parse(s).is_err(), "{s}");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::def::*;
+
+// WorkSingleを次の数ターンでどのプロジェクトに使うかの計画
+// 1ターンに1枚使い、手札のWorkSingleの他に、次のターンからは毎ターン無料のWorkSingle(2^L)が補充できるとみなす
+// 計画の価値は、完了するプロジェクトのvの合計から溢れた労働の罰則を引いて、使ったターン数で割ったもの
+// 完了で入れ替わったプロジェクトは分からないので、以降の対象にしない
+// ロールアウトの中でも呼ばれるので、探索するノードの数はPLAN_NODESまでで、超えた分の枝は打ち切る
+// 既定のplan_horizonは0で計画しない（seed 100-299で1: -0.069、2: -0.022、3: -0.155、2以上は制限時間も超える）
+const PLAN_NODES: usize = 200;
+
+pub struct Planner<'a> {
+    projects: &'a [Project],
+    // 今のカード以外の手札のWorkSingleの労働
+    hand: Vec<i64>,
+    incoming: i64,
+    horizon: usize,
+    alpha: f64,
+    beta: f64,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(
+        state: &'a State,
+        slot: Option<usize>,
+        horizon: usize,
+        alpha: f64,
+        beta: f64,
+    ) -> Planner<'a> {
+        let mut hand: Vec<i64> = state
+            .cards
+            .iter()
+            .enumerate()
+            .filter(|&(c, _)| Some(c) != slot)
+            .filter_map(|(_, card)| match card {
+                Card::WorkSingle(w) => Some(*w),
+                _ => None,
+            })
+            .collect();
+        hand.sort_unstable_by(|a, b| b.cmp(a));
+        Planner {
+            projects: &state.projects,
+            hand,
+            incoming: 1 << state.invest_level,
+            horizon,
+            alpha,
+            beta,
+        }
+    }
+
+    // 労働wのカードを今使う対象と計画の価値、allowedの対象のうちで何も完了しない場合はNone
+    pub fn target(&self, w: i64, allowed: impl Fn(usize) -> bool) -> Option<(usize, f64)> {
+        let mut best: Option<(usize, f64)> = None;
+        let targets: Vec<usize> = (0..self.projects.len()).filter(|&m| allowed(m)).collect();
+        for &m in targets.iter() {
+            let mut h: Vec<i64> = self.projects.iter().map(|p| p.h).collect();
+            let gained = self.work(&mut h, m, w);
+            let mut rate = 0.;
+            // 対象ごとに同じだけのノードを割り当てる
+            let mut nodes = PLAN_NODES / targets.len();
+            self.search(&mut h, 0, 1, m, gained, &mut rate, &mut nodes);
+            if rate > 0. && best.map_or(true, |(_, r)| rate > r) {
+                best = Some((m, rate));
+            }
+        }
+        best
+    }
+
+    // hは残りの必要な労働で、完了したプロジェクトは0
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        &self,
+        h: &mut [i64],
+        used: u32,
+        step: usize,
+        focus: usize,
+        gained: f64,
+        best: &mut f64,
+        nodes: &mut usize,
+    ) {
+        if *nodes == 0 {
+            return;
+        }
+        *nodes -= 1;
+        // 完了していない対象は、投入した労働の割合のbeta乗だけvを得たとみなす
+        let partial = if h[focus] > 0 {
+            (1. - h[focus] as f64 / self.projects[focus].h as f64).powf(self.beta)
+                * self.projects[focus].v as f64
+        } else {
+            0.
+        };
+        *best = best.max((gained + partial) / step as f64);
+        if step >= self.horizon {
+            return;
+        }
+        let mut cards: Vec<(Option<usize>, i64)> = vec![(None, self.incoming)];
+        for (i, &w) in self.hand.iter().enumerate() {
+            // 同じ労働のカードはどれを使っても同じなので最初の1枚だけ
+            if used >> i & 1 == 0 && (i == 0 || self.hand[i - 1] != w || used >> (i - 1) & 1 == 1) {
+                cards.push((Some(i), w));
+            }
+        }
+        for (i, w) in cards {
+            let used = match i {
+                Some(i) => used | 1 << i,
+                None => used,
+            };
+            // 完了できるプロジェクトか、今のカードの対象に続けて投入する
+            for m in 0..h.len() {
+                if h[m] == 0 || (w < h[m] && m != focus) {
+                    continue;
+                }
+                let before = h[m];
+                let gained = gained + self.work(h, m, w);
+                self.search(h, used, step + 1, focus, gained, best, nodes);
+                h[m] = before;
+            }
+        }
+    }
+
+    fn work(&self, h: &mut [i64], m: usize, w: i64) -> f64 {
+        if w < h[m] {
+            h[m] -= w;
+            return 0.;
+        }
+        let waste = w - h[m];
+        h[m] = 0;
+        self.projects[m].v as f64 - waste as f64 * self.alpha
+    }
+}
@@ -0,0 +1,64 @@
+use crate::def::*;
+
+const B_STEPS: usize = 240;
+const Z_STEPS: usize = 61;
+const Z_RANGE: f64 = 3.;
+const V_SIGMA: f64 = 0.5;
+
+// 補充されるプロジェクトの分布
+// b ~ U[2, 8], h = round(2^b), v = round(2^clamp(N(b, 0.5), 0, 10)) を 2^L 倍したもの
+// 数値積分で一度だけ計算しておく
+#[derive(Debug, Clone, Copy)]
+pub struct ProjectModel {
+    mean_h: f64,
+    mean_v: f64,
+}
+
+impl ProjectModel {
+    pub fn new() -> ProjectModel {
+        let z_weights: Vec<f64> = (0..Z_STEPS)
+            .map(|j| {
+                let z = z_of(j);
+                (-z * z / 2.).exp()
+            })
+            .collect();
+        let z_weight_sum = z_weights.iter().sum::<f64>();
+
+        let mut mean_h = 0.;
+        let mut mean_v = 0.;
+        for i in 0..B_STEPS {
+            let b = 2. + 6. * (i as f64 + 0.5) / B_STEPS as f64;
+            let h = 2f64.powf(b).round();
+            for (j, z_weight) in z_weights.iter().enumerate() {
+                let v = 2f64.powf((b + V_SIGMA * z_of(j)).clamp(0., 10.)).round();
+                let weight = z_weight / z_weight_sum / B_STEPS as f64;
+                mean_h += h * weight;
+                mean_v += v * weight;
+            }
+        }
+
+        ProjectModel { mean_h, mean_v }
+    }
+
+    pub fn expected_h(&self, invest_level: usize) -> f64 {
+        self.mean_h * scale(invest_level)
+    }
+
+    pub fn expected_v(&self, invest_level: usize) -> f64 {
+        self.mean_v * scale(invest_level)
+    }
+
+    // project を新しいプロジェクトに入れ替えた時の利得 (v - h) の増分の期待値
+    pub fn cancel_value(&self, project: &Project, invest_level: usize) -> f64 {
+        (self.expected_v(invest_level) - self.expected_h(invest_level))
+            - (project.v - project.h) as f64
+    }
+}
+
+fn z_of(j: usize) -> f64 {
+    -Z_RANGE + 2. * Z_RANGE * j as f64 / (Z_STEPS - 1) as f64
+}
+
+fn scale(invest_level: usize) -> f64 {
+    (1_i64 << invest_level) as f64
+}
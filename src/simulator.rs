@@ -2,9 +2,9 @@ use rand::{prelude::*, Rng};
 use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 use rand_distr::{Normal, WeightedIndex};
 
-use crate::{def::*, interactor::Interactor, solver::*, util::rnd};
+use crate::{def::*, interactor::Interactor, solver::*, trace::*, util::rnd};
 
-fn generate_project(rng: &mut ChaCha20Rng) -> Project {
+pub fn generate_project(rng: &mut ChaCha20Rng) -> Project {
     let b = rng.gen_range(2.0f64..=8.0);
     let h = 2.0f64.powf(b).round() as i64;
     let normal_dist = Normal::<f64>::new(b, 0.5).unwrap();
@@ -14,7 +14,7 @@ fn generate_project(rng: &mut ChaCha20Rng) -> Project {
     Project { h, v }
 }
 
-fn generate_card(rng: &mut ChaCha20Rng, m: usize, x: &Vec<i64>) -> (Card, i64) {
+pub fn generate_card(rng: &mut ChaCha20Rng, m: usize, x: &[i64]) -> Offer {
     let weighted_index = WeightedIndex::new(x).unwrap();
     let t = weighted_index.sample(rng);
     let mut w = 0;
@@ -40,7 +40,280 @@ fn generate_card(rng: &mut ChaCha20Rng, m: usize, x: &Vec<i64>) -> (Card, i64) {
         _ => panic!(),
     }
 
-    (Card::from_tw(t, w), p)
+    Offer {
+        card: Card::from_tw(t, w),
+        price: p,
+    }
+}
+
+// ローカルで1ゲームを再現するための入力と乱数列
+pub struct Game {
+    pub input: Input,
+    pub cards: Vec<Card>,
+    pub projects: Vec<Project>,
+    pub new_projects: Vec<Project>,
+    pub new_cards: Vec<Vec<Offer>>,
+}
+
+impl Game {
+    pub fn generate(seed: u64) -> Game {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let n = rng.gen_range(2..=7);
+        let m = rng.gen_range(2..=8);
+        let k = rng.gen_range(2..=5);
+        let t = 1000;
+        let x = vec![
+            20,
+            rng.gen_range(1..=10),
+            rng.gen_range(1..=10),
+            rng.gen_range(1..=5),
+            rng.gen_range(1..=3),
+        ];
+        let projects = (0..m).map(|_| generate_project(&mut rng)).collect();
+        let new_projects = (0..m * t).map(|_| generate_project(&mut rng)).collect();
+        let mut new_cards = vec![vec![]; t];
+        for cards in new_cards.iter_mut() {
+            cards.push(Offer {
+                card: Card::WorkSingle(1),
+                price: 0,
+            });
+            for _ in 1..k {
+                cards.push(generate_card(&mut rng, m, &x));
+            }
+        }
+        Game {
+            input: Input { n, m, k, t },
+            cards: vec![Card::WorkSingle(1); n],
+            projects,
+            new_projects,
+            new_cards,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Game, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Game::parse(&text)
+    }
+
+    // 入力ファイルと隠れた乱数列をまとめた形式、値は増資レベルで倍にする前のもの
+    // N M K T
+    // 初期の手札 t w (N行)
+    // 初期のプロジェクト h v (M行)
+    // 補充されるプロジェクト h v (M*T行、先頭から順に使う)
+    // 各ターンの補充の候補 t w p (T*K行、ターンごとにK行)
+    pub fn parse(text: &str) -> Result<Game, String> {
+        let x = text
+            .split_whitespace()
+            .map(|x| {
+                x.parse::<i64>()
+                    .map_err(|_| format!("invalid number: `{x}`"))
+            })
+            .collect::<Result<Vec<i64>, String>>()?;
+        if x.len() < 4 {
+            return Err("missing N M K T".to_string());
+        }
+        let (n, m, k, t) = (x[0] as usize, x[1] as usize, x[2] as usize, x[3] as usize);
+        if n == 0 || m == 0 || k == 0 {
+            return Err(format!("invalid N M K: {n} {m} {k}"));
+        }
+        let expected = 4 + 2 * n + 2 * m + 2 * m * t + 3 * t * k;
+        if x.len() != expected {
+            return Err(format!("expected {} numbers, found {}", expected, x.len()));
+        }
+
+        let card = |x: &[i64]| match x[0] {
+            0..=4 => Ok(Card::from_tw(x[0] as usize, x[1])),
+            _ => Err(format!("invalid card type: {}", x[0])),
+        };
+        let project = |x: &[i64]| Project { h: x[0], v: x[1] };
+        let (cards, x) = x[4..].split_at(2 * n);
+        let (projects, x) = x.split_at(2 * m);
+        let (new_projects, x) = x.split_at(2 * m * t);
+        let cards = cards.chunks(2).map(card).collect::<Result<_, _>>()?;
+        let projects = projects.chunks(2).map(project).collect();
+        let new_projects = new_projects.chunks(2).map(project).collect();
+        let new_cards = x
+            .chunks(3 * k)
+            .map(|turn| {
+                turn.chunks(3)
+                    .map(|x| {
+                        Ok(Offer {
+                            card: card(x)?,
+                            price: x[2],
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Game {
+            input: Input { n, m, k, t },
+            cards,
+            projects,
+            new_projects,
+            new_cards,
+        })
+    }
+
+    // LoggingInteractorのログから、見えた範囲の入力と乱数列を復元する
+    // 補充されたプロジェクトは、カードの効果から入れ替わった位置を求めて次の読み込みから取る
+    // 見えていない乱数列は含まないので、ログの出力を検証するのにだけ使える
+    pub fn parse_log(text: &str) -> Result<Game, String> {
+        let mut reads = text.lines().filter_map(|line| line.strip_prefix("# <"));
+        let mut outputs = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace())
+            .map(|x| {
+                x.parse::<usize>()
+                    .map_err(|_| format!("invalid output: `{x}`"))
+            });
+
+        let fields: Vec<&str> = reads
+            .next()
+            .ok_or("missing input line")?
+            .split('|')
+            .collect();
+        let (input, cards, projects) = match fields[..] {
+            [input, cards, projects] => (input, parse_cards(cards)?, parse_projects(projects)?),
+            _ => return Err("invalid input line".to_string()),
+        };
+        let input = match input
+            .split_whitespace()
+            .map(|x| x.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid input: `{input}`"))?[..]
+        {
+            [n, m, k, t] if cards.len() == n && projects.len() == m => Input { n, m, k, t },
+            _ => return Err(format!("invalid input: `{input}`")),
+        };
+
+        let mut game = Game {
+            input,
+            cards: cards.clone(),
+            projects: projects.clone(),
+            new_projects: vec![],
+            new_cards: vec![],
+        };
+        let mut cards = cards;
+        let mut projects = projects;
+        let mut invest_level = 0;
+        let mut money = 0;
+        for t in 0..input.t {
+            let Some(c) = outputs.next() else {
+                break;
+            };
+            let m = outputs.next().ok_or(format!("turn {t}: missing m"))??;
+            let action = Action::parse_use(c?, m, &cards).map_err(|e| format!("turn {t}: {e}"))?;
+            if m >= input.m {
+                return Err(format!("turn {t}: project {m} is out of range"));
+            }
+            let fields: Vec<&str> = reads
+                .next()
+                .ok_or(format!("turn {t}: missing read line"))?
+                .split('|')
+                .collect();
+            let (next_projects, next_money, offers) = match fields[..] {
+                [p, money, offers] => (
+                    parse_projects(p)?,
+                    money
+                        .trim()
+                        .parse::<i64>()
+                        .map_err(|_| format!("turn {t}: invalid money: `{money}`"))?,
+                    parse_offers(offers)?,
+                ),
+                _ => return Err(format!("turn {t}: invalid read line")),
+            };
+            if next_projects.len() != input.m || offers.len() != input.k {
+                return Err(format!(
+                    "turn {t}: expected {} projects and {} offers",
+                    input.m, input.k
+                ));
+            }
+
+            let mut replaced = vec![false; input.m];
+            match cards[action.slot().unwrap()] {
+                Card::WorkSingle(w) if projects[m].h <= w => replaced[m] = true,
+                Card::WorkAll(w) => {
+                    for (i, p) in projects.iter().enumerate() {
+                        replaced[i] = p.h <= w;
+                    }
+                }
+                Card::CancelSingle => replaced[m] = true,
+                Card::CancelAll => replaced = vec![true; input.m],
+                Card::Invest => invest_level += 1,
+                _ => {}
+            }
+            if let Card::WorkSingle(_) | Card::WorkAll(_) = cards[action.slot().unwrap()] {
+                for i in 0..input.m {
+                    if replaced[i] {
+                        money += projects[i].v;
+                    }
+                }
+            }
+            if money != next_money {
+                return Err(format!(
+                    "turn {t}: money {next_money} in the log, expected {money}"
+                ));
+            }
+            let scale = 1 << invest_level;
+            let unscale = |x: i64| match x % scale {
+                0 => Ok(x / scale),
+                _ => Err(format!("turn {t}: `{x}` is not a multiple of {scale}")),
+            };
+            for i in 0..input.m {
+                if replaced[i] {
+                    game.new_projects.push(Project {
+                        h: unscale(next_projects[i].h)?,
+                        v: unscale(next_projects[i].v)?,
+                    });
+                }
+            }
+            game.new_cards.push(
+                offers
+                    .iter()
+                    .map(|offer| {
+                        let card = match offer.card {
+                            Card::WorkSingle(w) => Card::WorkSingle(unscale(w)?),
+                            Card::WorkAll(w) => Card::WorkAll(unscale(w)?),
+                            card => card,
+                        };
+                        Ok(Offer {
+                            card,
+                            price: unscale(offer.price)?,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?,
+            );
+
+            let r = outputs.next().ok_or(format!("turn {t}: missing r"))??;
+            let offer = offers
+                .get(r)
+                .ok_or(format!("turn {t}: refill {r} is out of range"))?;
+            cards[action.slot().unwrap()] = offer.card;
+            money -= offer.price;
+            projects = next_projects;
+        }
+        Ok(game)
+    }
+
+    // seedならローカルのジェネレータ、それ以外はファイル
+    pub fn from_source(source: &str) -> Result<Game, String> {
+        match source.parse::<u64>() {
+            Ok(seed) => Ok(Game::generate(seed)),
+            Err(_) => Game::load(source),
+        }
+    }
+
+    pub fn initial_state(&self) -> State {
+        State {
+            turn: 0,
+            last_invest_round: 0,
+            invest_level: 0,
+            score: 0,
+            cards: self.cards.clone(),
+            projects: self.projects.clone(),
+        }
+    }
 }
 
 pub struct MockInteractor {
@@ -50,18 +323,19 @@ pub struct MockInteractor {
     cards: Vec<Card>,
     projects: Vec<Project>,
     used_card: usize,
-    candidate_cards: Vec<(Card, i64)>,
+    candidate_cards: Vec<Offer>,
     new_projects: Vec<Project>,
-    new_cards: Vec<Vec<(Card, i64)>>,
+    new_cards: Vec<Vec<Offer>>,
 }
 
 impl MockInteractor {
     pub fn new(
-        x: &Vec<i64>,
+        input: &Input,
+        x: &[i64],
         t: usize,
         state: &State,
         used_card: usize,
-        candidate_cards: Vec<(Card, i64)>,
+        candidate_cards: Vec<Offer>,
     ) -> MockInteractor {
         let mut rng = ChaCha20Rng::seed_from_u64(rnd::gen_range(0, 10000000000) as u64);
         let mut new_projects = vec![];
@@ -70,10 +344,13 @@ impl MockInteractor {
             new_projects.push(generate_project(&mut rng));
         }
         let mut new_cards = vec![vec![]; remain_t];
-        for i in 0..remain_t {
-            new_cards[i].push((Card::WorkSingle(1), 0));
-            for _ in 1..state.cards.len() {
-                new_cards[i].push(generate_card(&mut rng, state.projects.len(), &x));
+        for cards in new_cards.iter_mut() {
+            cards.push(Offer {
+                card: Card::WorkSingle(1),
+                price: 0,
+            });
+            for _ in 1..input.k {
+                cards.push(generate_card(&mut rng, state.projects.len(), x));
             }
         }
 
@@ -90,15 +367,29 @@ impl MockInteractor {
         }
     }
 
-    fn get_card_candidate(&mut self) -> Vec<(Card, i64)> {
+    pub fn from_game(game: &Game) -> MockInteractor {
+        MockInteractor {
+            t: 0,
+            score: 0,
+            invest_level: 0,
+            cards: game.cards.clone(),
+            projects: game.projects.clone(),
+            used_card: 0,
+            candidate_cards: vec![],
+            new_projects: game.new_projects.iter().rev().copied().collect(),
+            new_cards: game.new_cards.clone(),
+        }
+    }
+
+    fn get_card_candidate(&mut self) -> Vec<Offer> {
         let mut cards = self.new_cards[self.t].clone();
-        for card in cards.iter_mut() {
-            match &mut card.0 {
+        for offer in cards.iter_mut() {
+            match &mut offer.card {
                 Card::WorkSingle(w) => *w *= 1 << self.invest_level,
                 Card::WorkAll(w) => *w *= 1 << self.invest_level,
                 _ => {}
             }
-            card.1 *= 1 << self.invest_level;
+            offer.price *= 1 << self.invest_level;
         }
         self.t += 1;
         cards
@@ -121,7 +412,15 @@ impl MockInteractor {
 }
 
 impl Interactor for MockInteractor {
-    fn output_c(&mut self, c: usize, m: usize) {
+    fn output(&mut self, action: Action) {
+        let (c, m) = match action {
+            Action::Refill { offer } => {
+                self.score -= self.candidate_cards[offer].price;
+                self.cards[self.used_card] = self.candidate_cards[offer].card;
+                return;
+            }
+            _ => (action.slot().unwrap(), action.project()),
+        };
         match self.cards[c] {
             Card::WorkSingle(w) => {
                 self.work_project(m, w);
@@ -143,16 +442,8 @@ impl Interactor for MockInteractor {
         self.used_card = c;
     }
 
-    fn output_r(&mut self, r: usize) {
-        self.score -= self.candidate_cards[r].1;
-        self.cards[self.used_card] = self.candidate_cards[r].0;
-    }
-
     #[allow(unused)]
-    fn read_status(
-        &mut self,
-        input: &Input,
-    ) -> (Vec<crate::def::Project>, i64, Vec<(crate::def::Card, i64)>) {
+    fn read_status(&mut self, input: &Input) -> (Vec<Project>, i64, Vec<Offer>) {
         self.candidate_cards = self.get_card_candidate();
         (
             self.projects.clone(),
@@ -160,62 +451,241 @@ impl Interactor for MockInteractor {
             self.candidate_cards.clone(),
         )
     }
+
+    fn comment(&mut self, _message: &str) {}
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn montecarlo(
     rounds: usize,
-    cur_state: &State,
-    param: &Param,
+    cur_solver: &Solver,
     input: &Input,
     cur_t: usize,
-    x: &Vec<i64>,
+    x: &[i64],
     refill_first: bool,
     new_select_card: usize,
-    new_cards: &Vec<(Card, i64)>,
+    new_cards: &[Offer],
 ) -> i64 {
-    let mut score_sum = 0;
-
-    for _ in 0..rounds {
-        let mut solver = Solver {
-            param: param.clone(),
-            state: cur_state.clone(),
-        };
-        let mut mock_interactor = MockInteractor::new(
-            x,
-            cur_t,
-            &solver.state,
-            solver.state.empty_card_index().unwrap_or(0),
-            new_cards.clone(),
-        );
-
-        // 最初のrefillを固定する場合
-        if refill_first {
-            solver
-                .state
-                .refill_card(new_select_card, new_cards, &mut mock_interactor);
-        }
-        let start_t = if refill_first { cur_t + 1 } else { cur_t };
-        for t in start_t..input.t {
-            // 今持っているカードを見て、使うカードを決める
-            let (select_card, m) = solver.select_use_card(t);
-
-            if solver.state.cards[select_card] == Card::Invest {
-                solver.state.last_invest_round = t;
+    let scores = rollout(
+        rounds,
+        cur_solver,
+        input,
+        cur_t,
+        x,
+        new_cards,
+        |solver, interactor| {
+            // 最初のrefillを固定する場合
+            if refill_first {
+                solver
+                    .state
+                    .refill_card(new_select_card, new_cards, interactor);
+                cur_t + 1
+            } else {
+                cur_t
             }
-            solver.state.use_card(select_card, m, &mut mock_interactor);
-            let new_cards = solver.state.read_status(input, &mut mock_interactor);
+        },
+    );
+    scores.iter().sum::<i64>() / rounds as i64
+}
 
-            // 新しいカードを見て、補充するカードを決める
-            let new_card = if t < input.t - 1 {
-                solver.select_new_card(&new_cards, t)
+// カードを使う前の状態から、actionでカードを使った場合の最終スコアを並べる
+pub fn montecarlo_use(
+    rounds: usize,
+    cur_solver: &Solver,
+    input: &Input,
+    cur_t: usize,
+    x: &[i64],
+    action: Action,
+) -> Vec<i64> {
+    rollout(
+        rounds,
+        cur_solver,
+        input,
+        cur_t,
+        x,
+        &[],
+        |solver, interactor| {
+            if let Action::Invest { .. } = action {
+                solver.state.last_invest_round = cur_t;
+            }
+            solver.state.use_card(action, interactor);
+            let new_cards = solver.state.read_status(input, interactor);
+            let new_card = if cur_t < input.t - 1 {
+                solver.select_new_card(&new_cards, cur_t)
             } else {
                 0
             };
-            solver
-                .state
-                .refill_card(new_card, &new_cards, &mut mock_interactor);
+            solver.state.refill_card(new_card, &new_cards, interactor);
+            cur_t + 1
+        },
+    )
+}
+
+// 補充の候補がnew_cardsの状態から、rを補充した場合の最終スコアを並べる
+pub fn montecarlo_refill(
+    rounds: usize,
+    cur_solver: &Solver,
+    input: &Input,
+    cur_t: usize,
+    x: &[i64],
+    r: usize,
+    new_cards: &[Offer],
+) -> Vec<i64> {
+    rollout(
+        rounds,
+        cur_solver,
+        input,
+        cur_t,
+        x,
+        new_cards,
+        |solver, interactor| {
+            solver.state.refill_card(r, new_cards, interactor);
+            cur_t + 1
+        },
+    )
+}
+
+// 補充の候補がnew_cardsの状態から、最初の行動をfirstで進めて、残りをランダムな乱数列で最後まで進める
+// firstは次に進めるターンを返す
+fn rollout<F: Fn(&mut Solver, &mut MockInteractor) -> usize>(
+    rounds: usize,
+    cur_solver: &Solver,
+    input: &Input,
+    cur_t: usize,
+    x: &[i64],
+    new_cards: &[Offer],
+    first: F,
+) -> Vec<i64> {
+    (0..rounds)
+        .map(|_| {
+            let mut solver = cur_solver.clone();
+            let mut mock_interactor = MockInteractor::new(
+                input,
+                x,
+                cur_t,
+                &solver.state,
+                solver.state.empty_card_index().unwrap_or(0),
+                new_cards.to_vec(),
+            );
+            let start_t = first(&mut solver, &mut mock_interactor);
+            play_rest(&mut solver, input, start_t, &mut mock_interactor);
+            solver.state.score
+        })
+        .collect()
+}
+
+// ゲームを最初から最後まで進めて最終スコアを返す（終盤のモンテカルロはしない）
+pub fn play_game(game: &Game, solver: &mut Solver) -> i64 {
+    let mut interactor = MockInteractor::from_game(game);
+    play_rest(solver, &game.input, 0, &mut interactor);
+    solver.state.score
+}
+
+fn play_rest(solver: &mut Solver, input: &Input, start_t: usize, interactor: &mut MockInteractor) {
+    for t in start_t..input.t {
+        // 今持っているカードを見て、使うカードを決める
+        let action = solver.select_use_card(t);
+
+        if let Action::Invest { .. } = action {
+            solver.state.last_invest_round = t;
         }
-        score_sum += solver.state.score;
+        solver.state.use_card(action, interactor);
+        let new_cards = solver.state.read_status(input, interactor);
+
+        // 新しいカードを見て、補充するカードを決める
+        let new_card = if t < input.t - 1 {
+            solver.select_new_card(&new_cards, t)
+        } else {
+            0
+        };
+        solver.state.refill_card(new_card, &new_cards, interactor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // N=2 M=2 K=2 T=2 の小さいゲーム
+    const GAME: &str = "2 2 2 2
+0 1
+0 1
+1 5
+3 7
+2 2
+4 4
+8 8
+16 16
+0 1 0
+4 0 300
+0 1 0
+2 0 5
+";
+
+    #[test]
+    fn parse_game() {
+        let game = Game::parse(GAME).unwrap();
+        assert_eq!(
+            (game.input.n, game.input.m, game.input.k, game.input.t),
+            (2, 2, 2, 2)
+        );
+        assert_eq!(game.cards, vec![Card::WorkSingle(1); 2]);
+        assert_eq!(
+            game.projects,
+            vec![Project { h: 1, v: 5 }, Project { h: 3, v: 7 }]
+        );
+        assert_eq!(game.new_projects.len(), 4);
+        assert_eq!(
+            game.new_cards[0],
+            vec![
+                Offer {
+                    card: Card::WorkSingle(1),
+                    price: 0
+                },
+                Offer {
+                    card: Card::Invest,
+                    price: 300
+                },
+            ]
+        );
+        assert_eq!(game.new_cards[1][1].card, Card::CancelSingle);
+    }
+
+    #[test]
+    fn reject_invalid_game() {
+        assert!(Game::parse("").is_err());
+        assert!(Game::parse("0 2 2 2").is_err());
+        assert!(Game::parse(&GAME.replace("2 0 5", "2 0")).is_err());
+        assert!(Game::parse(&GAME.replace("2 0 5", "2 0 5 1")).is_err());
+        assert!(Game::parse(&GAME.replace("2 0 5", "5 0 5")).is_err());
+        assert!(Game::parse(&GAME.replace("2 0 5", "2 0 x")).is_err());
+    }
+
+    // GAMEをLoggingInteractorで記録した場合のログ
+    const LOG: &str = "# < 2 2 2 2 | 0:1 0:1 | 1:5 3:7
+0 0
+# < 2:2 3:7 | 5 | 0:1:0 4:0:300
+0
+0 1
+# < 2:2 2:7 | 5 | 0:1:0 2:0:5
+0
+";
+
+    #[test]
+    fn parse_log() {
+        let game = Game::parse_log(LOG).unwrap();
+        let original = Game::parse(GAME).unwrap();
+        assert_eq!(game.cards, original.cards);
+        assert_eq!(game.projects, original.projects);
+        assert_eq!(game.new_projects, original.new_projects[..1]);
+        assert_eq!(game.new_cards, original.new_cards);
+        assert_eq!(crate::judge::check(&game, LOG), Ok(5));
+    }
+
+    #[test]
+    fn reject_inconsistent_log() {
+        assert!(Game::parse_log(&LOG.replace("| 5 |", "| 6 |")).is_err());
+        assert!(Game::parse_log(&LOG.replace("0 1\n", "0 2\n")).is_err());
+        assert!(Game::parse_log(&LOG.replace("4:0:300", "4:0:300 0:1:0")).is_err());
     }
-    score_sum / rounds as i64
 }
@@ -0,0 +1,502 @@
+use crate::def::*;
+use crate::expectimax::*;
+use crate::json::*;
+use crate::param_table::*;
+use crate::project_model::*;
+use crate::solver::*;
+use crate::trace::*;
+
+// カードを使う前か、使った後で補充する前か
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Use,
+    Refill,
+}
+
+// ある時点の状態、explainやモンテカルロの開始点に使う
+// offersは補充する前だけ意味を持つ
+// xは補充の候補の種類ごとの出現回数で、モンテカルロの乱数列の生成に使う
+//
+// プロジェクトの履歴は保存しないので、読み込んだ時は全て新しく引いたものとみなす
+//
+// テキスト形式（#以降はコメント、offersとxは省略可）:
+// input <n> <m> <k> <t>
+// turn <t> <use|refill>
+// level <invest_level>
+// money <money>
+// last_invest <t>
+// cards <t:w ...>
+// projects <h:v ...>
+// offers <t:w:p ...>
+// x <5個の整数>
+//
+// JSON形式はキーが同じで、inputは{"n","m","k","t"}、cardsは[t, w]、projectsは[h, v]、offersは[t, w, p]の配列
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub input: Input,
+    pub t: usize,
+    pub phase: Phase,
+    pub state: State,
+    pub offers: Vec<Offer>,
+    pub x: Vec<i64>,
+}
+
+impl Snapshot {
+    // 所持金は前のターンの補充で払った分を引く前の値で、ソルバーが見ていたものと同じ
+    // Refillではプロジェクトと所持金を次のターンの記録から戻す（最終ターンではプロジェクトは分からないので使う前のもの）
+    pub fn from_trace(trace: &Trace, turn: usize, phase: Phase) -> Result<Snapshot, String> {
+        let Some(record) = trace.turns.get(turn) else {
+            return Err(format!(
+                "turn {} is out of range (0..{})",
+                turn,
+                trace.turns.len()
+            ));
+        };
+        let last_invest_round = trace.turns[..turn]
+            .iter()
+            .rev()
+            .find(|turn| matches!(turn.action, Action::Invest { .. }))
+            .map(|turn| turn.t)
+            .unwrap_or(0);
+        let mut state = State {
+            turn,
+            last_invest_round,
+            invest_level: record.invest_level,
+            score: record.money,
+            cards: record.cards.clone(),
+            projects: record.projects.clone(),
+        };
+        // 補充の候補の出やすさは、このターンまでに見た候補から推定する（ソルバーと同じ）
+        let mut x = vec![0; 5];
+        for offer in trace.turns[..=turn]
+            .iter()
+            .flat_map(|turn| turn.offers.iter())
+        {
+            x[offer.card.to_t()] += 1;
+        }
+
+        let mut offers = vec![];
+        if phase == Phase::Refill {
+            if let Action::Invest { .. } = record.action {
+                state.invest_level += 1;
+                state.last_invest_round = turn;
+            }
+            state.cards[record.action.slot().unwrap()] = Card::None;
+            match trace.turns.get(turn + 1) {
+                Some(next) => {
+                    state.projects = next.projects.clone();
+                    state.score = next.money;
+                }
+                None => state.score = trace.score,
+            }
+            offers = record.offers.clone();
+        }
+
+        Ok(Snapshot {
+            input: trace.input(),
+            t: turn,
+            phase,
+            state,
+            offers,
+            x,
+        })
+    }
+
+    pub fn solver(&self, param_table: &ParamTable, evaluator: &Evaluator) -> Solver {
+        Solver {
+            state: self.state.clone(),
+            param: param_table.lookup(&self.input),
+            project_model: ProjectModel::new(),
+            evaluator: evaluator.clone(),
+            verbosity: 0,
+            policy: Policy::Greedy,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Snapshot, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Snapshot::parse(&text)
+    }
+
+    // 先頭が{ならJSON、それ以外はテキスト形式
+    pub fn parse(text: &str) -> Result<Snapshot, String> {
+        if text.trim_start().starts_with('{') {
+            Snapshot::parse_json(text)
+        } else {
+            Snapshot::parse_text(text)
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut s = format!(
+            "input {} {} {} {}\n",
+            self.input.n, self.input.m, self.input.k, self.input.t
+        );
+        s += &format!("turn {} {}\n", self.t, phase_name(self.phase));
+        s += &format!("level {}\n", self.state.invest_level);
+        s += &format!("money {}\n", self.state.score);
+        s += &format!("last_invest {}\n", self.state.last_invest_round);
+        s += &format!("cards {}\n", format_cards(&self.state.cards));
+        s += &format!("projects {}\n", format_projects(&self.state.projects));
+        if !self.offers.is_empty() {
+            s += &format!("offers {}\n", format_offers(&self.offers));
+        }
+        s += &format!("x {}\n", join(&self.x));
+        s
+    }
+
+    pub fn to_json(&self) -> String {
+        let cards = self
+            .state
+            .cards
+            .iter()
+            .map(|card| match card {
+                Card::None => "[5,0]".to_string(),
+                _ => format!("[{},{}]", card.to_t(), card_w(card)),
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let projects = self
+            .state
+            .projects
+            .iter()
+            .map(|p| format!("[{},{}]", p.h, p.v))
+            .collect::<Vec<String>>()
+            .join(",");
+        let offers = self
+            .offers
+            .iter()
+            .map(|o| format!("[{},{},{}]", o.card.to_t(), card_w(&o.card), o.price))
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            r#"{{"input":{{"n":{},"m":{},"k":{},"t":{}}},"turn":{},"phase":"{}","level":{},"money":{},"last_invest":{},"cards":[{}],"projects":[{}],"offers":[{}],"x":[{}]}}"#,
+            self.input.n,
+            self.input.m,
+            self.input.k,
+            self.input.t,
+            self.t,
+            phase_name(self.phase),
+            self.state.invest_level,
+            self.state.score,
+            self.state.last_invest_round,
+            cards,
+            projects,
+            offers,
+            self.x
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+
+    fn parse_text(text: &str) -> Result<Snapshot, String> {
+        let mut fields: Vec<(&str, &str)> = vec![];
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            fields.push(line.split_once(' ').unwrap_or((line, "")));
+        }
+        let get = |key: &str| {
+            fields
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| format!("missing `{key}`"))
+        };
+        let numbers = |key: &str| -> Result<Vec<i64>, String> {
+            get(key)?
+                .split_whitespace()
+                .map(|x| x.parse().map_err(|_| format!("invalid {key}: `{x}`")))
+                .collect()
+        };
+        let number = |key: &str| -> Result<i64, String> {
+            numbers(key)?
+                .first()
+                .copied()
+                .ok_or_else(|| format!("missing value for `{key}`"))
+        };
+
+        let input = numbers("input")?;
+        if input.len() != 4 {
+            return Err(format!("input expects 4 values, found {}", input.len()));
+        }
+        let (turn, phase) = get("turn")?
+            .split_once(' ')
+            .ok_or("turn expects `<t> <phase>`")?;
+        let t: usize = turn
+            .parse()
+            .map_err(|_| format!("invalid turn: `{turn}`"))?;
+        let projects = parse_projects(get("projects")?)?;
+        let snapshot = Snapshot {
+            input: Input {
+                n: input[0] as usize,
+                m: input[1] as usize,
+                k: input[2] as usize,
+                t: input[3] as usize,
+            },
+            t,
+            phase: parse_phase(phase.trim())?,
+            state: State {
+                turn: t,
+                last_invest_round: number("last_invest")? as usize,
+                invest_level: number("level")? as usize,
+                score: number("money")?,
+                cards: parse_cards(get("cards")?)?,
+                projects,
+            },
+            offers: get("offers").map_or(Ok(vec![]), parse_offers)?,
+            x: if get("x").is_ok() {
+                numbers("x")?
+            } else {
+                default_x()
+            },
+        };
+        snapshot.validate()?;
+        Ok(snapshot)
+    }
+
+    fn parse_json(text: &str) -> Result<Snapshot, String> {
+        let json = Json::parse(text)?;
+        let get = |key: &str| json.get(key).ok_or_else(|| format!("missing `{key}`"));
+        let number = |json: &Json, key: &str| {
+            json.get(key)
+                .and_then(|x| x.as_i64())
+                .ok_or_else(|| format!("missing or invalid `{key}`"))
+        };
+        let tuples = |key: &str, len: usize| -> Result<Vec<Vec<i64>>, String> {
+            let values = match json.get(key) {
+                Some(values) => values
+                    .as_array()
+                    .ok_or_else(|| format!("`{key}` must be an array"))?,
+                None => return Ok(vec![]),
+            };
+            values
+                .iter()
+                .map(|x| match x.as_i64_vec() {
+                    Some(x) if x.len() == len => Ok(x),
+                    _ => Err(format!("`{key}` expects arrays of {len} integers")),
+                })
+                .collect()
+        };
+        // 手札の空きは[5,0]、補充の候補に空きはない
+        let card = |x: &[i64], max_t: i64| match x[0] {
+            t if (0..=max_t).contains(&t) => Ok(Card::from_tw(t as usize, x[1])),
+            _ => Err(format!("invalid card: {:?}", x)),
+        };
+
+        let input = get("input")?;
+        let t = number(&json, "turn")? as usize;
+        let projects: Vec<Project> = tuples("projects", 2)?
+            .iter()
+            .map(|x| Project { h: x[0], v: x[1] })
+            .collect();
+        let snapshot = Snapshot {
+            input: Input {
+                n: number(input, "n")? as usize,
+                m: number(input, "m")? as usize,
+                k: number(input, "k")? as usize,
+                t: number(input, "t")? as usize,
+            },
+            t,
+            phase: parse_phase(get("phase")?.as_str().ok_or("`phase` must be a string")?)?,
+            state: State {
+                turn: t,
+                last_invest_round: number(&json, "last_invest")? as usize,
+                invest_level: number(&json, "level")? as usize,
+                score: number(&json, "money")?,
+                cards: tuples("cards", 2)?
+                    .iter()
+                    .map(|x| card(x, 5))
+                    .collect::<Result<_, _>>()?,
+                projects,
+            },
+            offers: tuples("offers", 3)?
+                .iter()
+                .map(|x| {
+                    Ok(Offer {
+                        card: card(x, 4)?,
+                        price: x[2],
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            x: match json.get("x") {
+                Some(x) => x.as_i64_vec().ok_or("`x` must be an array of integers")?,
+                None => default_x(),
+            },
+        };
+        snapshot.validate()?;
+        Ok(snapshot)
+    }
+
+    // 手で編集した時の食い違いを読み込む時点で弾く
+    fn validate(&self) -> Result<(), String> {
+        if self.state.cards.len() != self.input.n {
+            return Err(format!(
+                "expected {} cards, found {}",
+                self.input.n,
+                self.state.cards.len()
+            ));
+        }
+        if self.state.projects.len() != self.input.m {
+            return Err(format!(
+                "expected {} projects, found {}",
+                self.input.m,
+                self.state.projects.len()
+            ));
+        }
+        if self.x.len() != 5 || self.x.iter().any(|&x| x < 0) || self.x.iter().all(|&x| x == 0) {
+            return Err(format!(
+                "x expects 5 non-negative counts with a positive one, found {:?}",
+                self.x
+            ));
+        }
+        let empty = self.state.empty_card_index().is_some();
+        match self.phase {
+            Phase::Use if empty => Err("cards must not be empty before use".to_string()),
+            Phase::Refill if !empty => {
+                Err("one card must be empty (`-`) before refill".to_string())
+            }
+            Phase::Refill if self.offers.len() != self.input.k => Err(format!(
+                "expected {} offers, found {}",
+                self.input.k,
+                self.offers.len()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// xを省略した場合は、公式の生成でのおおよその期待値
+fn default_x() -> Vec<i64> {
+    vec![20, 5, 5, 3, 2]
+}
+
+fn phase_name(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Use => "use",
+        Phase::Refill => "refill",
+    }
+}
+
+fn parse_phase(s: &str) -> Result<Phase, String> {
+    match s {
+        "use" => Ok(Phase::Use),
+        "refill" => Ok(Phase::Refill),
+        _ => Err(format!("invalid phase: `{s}`")),
+    }
+}
+
+fn join(x: &[i64]) -> String {
+    x.iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// トレースのあるターンの状態をスナップショットとして書き出す
+// usage: snapshot <trace|seed> <turn> [use|refill] [json]
+pub fn run(args: &[String], param_table: &ParamTable, evaluator: &Evaluator) {
+    let source = args.first().map(|s| s.as_str()).unwrap_or("trace.log");
+    let turn: usize = args.get(1).map(|s| s.parse().unwrap()).unwrap_or(0);
+    let phase = args
+        .get(2)
+        .map(|s| parse_phase(s).unwrap())
+        .unwrap_or(Phase::Use);
+    let json = args.get(3).is_some_and(|s| s == "json");
+
+    let trace = Trace::from_source(source, param_table, evaluator);
+    let snapshot = match Snapshot::from_trace(&trace, turn, phase) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    if json {
+        println!("{}", snapshot.to_json());
+    } else {
+        print!("{}", snapshot.to_text());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "input 2 2 2 1000 # n m k t
+turn 5 refill
+level 1
+money 12
+last_invest 3
+cards 0:2 -
+projects 40:50 6:8
+offers 0:2:0 1:4:9
+x 9 1 1 0 1
+";
+
+    #[test]
+    fn round_trip_text_and_json() {
+        let snapshot = Snapshot::parse(TEXT).unwrap();
+        assert_eq!(snapshot.t, 5);
+        assert_eq!(snapshot.phase, Phase::Refill);
+        assert_eq!(snapshot.state.cards, vec![Card::WorkSingle(2), Card::None]);
+        assert_eq!(snapshot.offers[1].card, Card::WorkAll(4));
+
+        let text = snapshot.to_text();
+        assert_eq!(Snapshot::parse(&text).unwrap().to_text(), text);
+        let json = snapshot.to_json();
+        assert_eq!(Snapshot::parse(&json).unwrap().to_text(), text);
+        assert_eq!(Snapshot::parse(&json).unwrap().to_json(), json);
+    }
+
+    #[test]
+    fn default_x_when_omitted() {
+        let snapshot = Snapshot::parse(&TEXT.replace("x 9 1 1 0 1\n", "")).unwrap();
+        assert_eq!(snapshot.x, default_x());
+    }
+
+    #[test]
+    fn reject_invalid_text() {
+        for (from, to) in [
+            ("input 2 2 2 1000", "input 2 2 2"),
+            ("turn 5 refill", "turn 5"),
+            ("turn 5 refill", "turn 5 later"),
+            ("cards 0:2 -", "cards 0:2"),
+            ("cards 0:2 -", "cards 0:2 0:1"),
+            ("projects 40:50 6:8", "projects 40:50"),
+            ("offers 0:2:0 1:4:9", "offers 0:2:0"),
+            ("x 9 1 1 0 1", "x 9 1 1 -1 1"),
+            ("x 9 1 1 0 1", "x 0 0 0 0 0"),
+            ("x 9 1 1 0 1", "x 9 1 1 0"),
+            ("money 12", ""),
+        ] {
+            let text = TEXT.replace(from, to);
+            assert!(Snapshot::parse(&text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn reject_invalid_json() {
+        let json = Snapshot::parse(TEXT).unwrap().to_json();
+        for (from, to) in [
+            (r#""phase":"refill""#, r#""phase":"later""#),
+            (r#""phase":"refill""#, r#""phase":1"#),
+            (r#""phase":"refill""#, r#""phase":"re\qfill""#),
+            (r#""cards":[[0,2],[5,0]]"#, r#""cards":[[0,2],[6,0]]"#),
+            (
+                r#""offers":[[0,2,0],[1,4,9]]"#,
+                r#""offers":[[0,2,0],[5,0,9]]"#,
+            ),
+            (r#""x":[9,1,1,0,1]"#, r#""x":[9,1,1,-1,1]"#),
+            (r#""money":12,"#, ""),
+        ] {
+            let text = json.replace(from, to);
+            assert_ne!(text, json);
+            assert!(Snapshot::parse(&text).is_err(), "{text}");
+        }
+    }
+}
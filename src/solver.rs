@@ -1,73 +1,109 @@
+use crate::card_valuer::*;
 use crate::def::*;
+use crate::expectimax::*;
+use crate::forecast::*;
+use crate::hand::*;
 use crate::interactor::*;
+use crate::learned::*;
+use crate::project_model::*;
 use crate::simulator::*;
+use crate::stockpile::*;
+use crate::trace::*;
 
+// このターン以降の補充はモンテカルロで選ぶ
+pub const MONTE_CARLO_TURN: usize = 990;
+
+// 増資以外のカードの評価に使う関数
+#[derive(Clone)]
+pub enum Evaluator {
+    Param,
+    Learned(LearnedModel),
+}
+
+#[derive(Clone)]
 pub struct Solver {
     pub state: State,
     pub param: Param,
+    pub project_model: ProjectModel,
+    pub evaluator: Evaluator,
+    // 出力するコメントの詳しさ
+    // 0: なし、1: 選んだ行動と増資の条件、2: 全ての候補の評価値
+    pub verbosity: usize,
+    pub policy: Policy,
 }
 
 impl Solver {
-    pub fn remain_w(&self, t: usize, p: i64) -> i64 {
-        (999 - t as i64) * 2_i64.pow(self.state.invest_level as u32) + (self.state.score - p)
-    }
-
-    pub fn solve(&mut self, input: &Input, interactor: &mut IOInteractor) {
+    pub fn solve<I: Interactor>(&mut self, input: &Input, interactor: &mut I) -> Trace {
         let mut recorder = Recorder::new();
+        let mut trace = Trace::new(input);
+        // 補充の支払いを引いた所持金
+        let mut money = self.state.score;
 
         for t in 0..input.t {
+            let mut turn = TurnRecord {
+                t,
+                invest_level: self.state.invest_level,
+                money: self.state.score,
+                cards: self.state.cards.clone(),
+                projects: self.state.projects.clone(),
+                action: Action::UseSingle {
+                    slot: 0,
+                    project: 0,
+                },
+                offers: vec![],
+                refill: 0,
+                comments: vec![],
+            };
+
             // 今持っているカードを見て、使うカードを決める
-            let (select_card, m) = self.select_use_card(t);
+            let action = self.choose_use_card(input, t, &recorder.x, money);
+            for (level, message) in self.use_comments(t, action) {
+                self.comment(level, message, interactor, &mut turn);
+            }
 
-            if self.state.cards[select_card] == Card::Invest {
+            if let Action::Invest { .. } = action {
                 self.state.last_invest_round = t;
             }
-            self.state.use_card(select_card, m, interactor);
+            let prediction = self.state.predict(action, money);
+            self.state.use_card(action, interactor);
             recorder.scores.push(self.state.score);
 
             let new_cards = self.state.read_status(input, interactor);
-            for (card, _) in new_cards.iter() {
-                recorder.x[card.to_t()] += 1;
+            // ルールの理解やシミュレータとのずれは常に標準エラーに出し、コメントには詳細表示のときだけ出す
+            for mismatch in prediction.check(&self.state.projects, self.state.score) {
+                let message = format!("t={} mismatch {}", t, mismatch);
+                eprintln!("{}", message);
+                self.comment(1, message, interactor, &mut turn);
+            }
+            for offer in new_cards.iter() {
+                recorder.x[offer.card.to_t()] += 1;
             }
 
             // 新しいカードを見て、補充するカードを決める
-            const MONTE_CARLO_ROUND: usize = 100;
-            let new_card = if t < 990 {
-                self.select_new_card(&new_cards, t)
-            } else if t < input.t - 1 {
-                (0..new_cards.len())
-                    .max_by_key(|&i| {
-                        if new_cards[i].1 <= self.state.score {
-                            montecarlo(
-                                MONTE_CARLO_ROUND,
-                                &self.state,
-                                &self.param,
-                                input,
-                                t,
-                                &recorder.x,
-                                true,
-                                i,
-                                &new_cards,
-                            )
-                        } else {
-                            -1
-                        }
-                    })
-                    .unwrap()
-            } else {
-                0
-            };
+            let new_card = self.choose_refill(input, &new_cards, t, &recorder.x);
+            for (level, message) in
+                self.refill_comments(input, &new_cards, t, &recorder.x, &trace.turns, new_card)
+            {
+                self.comment(level, message, interactor, &mut turn);
+            }
             // モンテカルロしない場合
             // let new_card = if t < input.t - 1 {
             //     self.select_new_card(&new_cards, t)
             // } else {
             //     0
             // };
-            if new_cards[new_card].0 == Card::Invest {
+            if new_cards[new_card].card == Card::Invest {
                 recorder.invest_rounds.push(t);
             }
+            turn.action = action;
+            turn.offers = new_cards.clone();
+            turn.refill = new_card;
+            trace.turns.push(turn);
             self.state.refill_card(new_card, &new_cards, interactor);
+            money = self.state.score - new_cards[new_card].price;
         }
+        trace.score = self.state.score;
+        trace.invest_level = self.state.invest_level;
 
         // ビジュアライズ用
         if cfg!(feature = "local") {
@@ -75,138 +111,136 @@ impl Solver {
             let mut file = std::fs::File::create("score.log").unwrap();
             writeln!(&mut file, "{:?}", recorder.scores).unwrap();
             writeln!(&mut file, "{:?}", recorder.invest_rounds).unwrap();
+            trace.write("trace.log");
         }
+        trace
     }
 
-    pub fn eval(&self, card: &Card, p: i64, t: usize, refill: bool) -> (f64, usize) {
-        if p > self.state.score {
-            return (-INF, 0);
+    fn comment<I: Interactor>(
+        &self,
+        level: usize,
+        message: String,
+        interactor: &mut I,
+        turn: &mut TurnRecord,
+    ) {
+        if level <= self.verbosity {
+            interactor.comment(&message);
+            turn.comments.push(message);
         }
+    }
 
-        let overflow_alpha = if refill {
-            self.param.overflow_alpha_refill
-        } else {
-            self.param.overflow_alpha
-        };
-        let overflow_alpha_all = if refill {
-            self.param.overflow_alpha_all_refill
-        } else {
-            self.param.overflow_alpha_all
-        };
-        let cancel_alpha = if refill {
-            self.param.cancel_alpha_refill
-        } else {
-            self.param.cancel_alpha
-        };
-        let cancel_alpha_all = if refill {
-            self.param.cancel_alpha_all_refill
-        } else {
-            self.param.cancel_alpha_all
-        };
-
-        match card {
-            Card::WorkSingle(w) => {
-                let m = (0..self.state.projects.len())
-                    .max_by_key(|&i| {
-                        if self.state.projects[i].h > w + self.remain_w(t, p) {
-                            return -INF as i64 - self.state.projects[i].h;
-                        }
-                        (((*w as f64 / self.state.projects[i].h as f64)
-                            .min(1.)
-                            .powf(self.param.work_single_beta)
-                            * self.state.projects[i].v as f64
-                            - ((w - self.state.projects[i].h).max(0) as f64))
-                            * 10000.) as i64
-                    })
-                    .unwrap();
-                if self.state.projects[m].h > w + self.remain_w(t, p) && p > 0 {
-                    return (-INF, m);
-                }
-                let eval = *w as f64
-                    - p as f64
-                    - ((w - self.state.projects[m].h).max(0) as f64) * overflow_alpha;
-                (eval, m)
+    // (詳しさ, コメント) を並べる、必要な詳しさの分だけ作る
+    fn use_comments(&self, t: usize, action: Action) -> Vec<(usize, String)> {
+        let mut comments = vec![];
+        if self.verbosity >= 2 {
+            for (c, card) in self.state.cards.iter().enumerate() {
+                let (value, m) = self.eval(card, t);
+                comments.push((2, format!("eval {} {:?} {:.3} m={}", c, card, value, m)));
             }
-            Card::WorkAll(w) => {
-                let mut projects = self.state.projects.clone();
-                projects.sort_by_key(|p| p.h);
-                let feasible_proj_count = {
-                    let mut c = 0;
-                    let mut remain_w = self.remain_w(t, p);
-                    for p in projects {
-                        if p.h - w <= remain_w {
-                            remain_w -= p.h - w; // NOTE: max(0)を取るのが正しいが、取らない方がスコアが良い
-                            c += 1;
-                        }
-                    }
-                    c
-                };
-                let w_sum = (*w * feasible_proj_count) as f64;
-                let eval = w_sum
-                    - p as f64
-                    - (self
-                        .state
-                        .projects
-                        .iter()
-                        .map(|proj| (*w as f64 - proj.h as f64).max(0.) * overflow_alpha_all)
-                        .sum::<f64>());
-                (eval, 0)
-            }
-            Card::CancelSingle => {
-                let m = (0..self.state.projects.len())
-                    .max_by_key(|&i| {
-                        ((self.state.projects[i].h as f64 - self.state.projects[i].v as f64)
-                            / self.state.projects[i].h as f64 // TODO: 消す？
-                            * 10000.)
-                            .round() as i64
-                    })
-                    .unwrap();
-                if t >= self.param.cancel_limit {
-                    return (-INF, m);
-                }
-                let eval = self.state.projects[m].h as f64 * cancel_alpha
-                    - self.state.projects[m].v as f64
-                    - p as f64;
-                (eval, m)
+        }
+        if self.verbosity >= 1 {
+            let select_card = action.slot().unwrap();
+            let card = self.state.cards[select_card];
+            let mut message = format!(
+                "t={} use {} {:?} on {}",
+                t,
+                select_card,
+                card,
+                action.project()
+            );
+            if card == Card::Invest {
+                message += &format!(" (invest rule {})", self.invest_rule(t).unwrap_or(0));
             }
-            Card::CancelAll => {
-                if t >= self.param.cancel_limit {
-                    return (-INF, 0);
-                }
-                let eval = self
-                    .state
-                    .projects
-                    .iter()
-                    .map(|proj| proj.h as f64 * cancel_alpha_all - proj.v as f64)
-                    .sum::<f64>()
-                    - p as f64;
-                (eval, 0)
+            let stockpile = StockPlan::new(&self.state, &self.param, t).held();
+            if !stockpile.is_empty() {
+                message += &format!(" (stockpile {:?})", stockpile);
             }
-            Card::Invest => {
-                if self.state.invest_level >= MAX_INVEST_LEVEL {
-                    return (-INF, 0);
-                }
-                let invest_card_count = self
-                    .state
-                    .cards
-                    .iter()
-                    .filter(|&&card| card == Card::Invest)
-                    .count();
+            comments.push((1, message));
+        }
+        comments
+    }
 
-                // 1. 手持ちのカードが全て増資になった場合
-                // 2. 増資の期限が来た場合
-                // 3. 前回増資した場合（増資カードを消費している場合）
-                // 4. 増資回数がMAX_INVEST_LEVELに到達する場合
-                if self.state.cards.len() == invest_card_count
-                    || ((t >= self.param.invest_limit || self.state.last_invest_round + 1 == t)
-                        && p == 0)
-                    || invest_card_count + self.state.invest_level == MAX_INVEST_LEVEL
-                {
-                    return (INF, 0);
-                }
-                (-INF, 0)
+    fn refill_comments(
+        &self,
+        input: &Input,
+        new_cards: &[Offer],
+        t: usize,
+        x: &[i64],
+        history: &[TurnRecord],
+        new_card: usize,
+    ) -> Vec<(usize, String)> {
+        let mut comments = vec![];
+        if self.verbosity >= 2 {
+            for (r, Offer { card, price: p }) in new_cards.iter().enumerate() {
+                let value = self.eval_refill(card, *p, t);
+                comments.push((
+                    2,
+                    format!("eval_refill {} {:?} {} {:.3}", r, card, p, value),
+                ));
+            }
+        }
+        if self.verbosity >= 1 {
+            let Offer { card, price: p } = new_cards[new_card];
+            let mut message = format!("t={} refill {} {:?} for {}", t, new_card, card, p);
+            if card == Card::Invest {
+                let rule = self.refill_invest_rule(p, t).unwrap_or(0);
+                let forecast = Forecast::new(input, &self.state, new_cards, x, history);
+                message += &format!(
+                    " (invest rule {}, payback {})",
+                    rule,
+                    format_turns(forecast.payback(p))
+                );
             }
-            Card::None => (-INF, 0),
+            comments.push((1, message));
+        }
+        comments
+    }
+
+    // 方策に従って使うカードを選ぶ
+    // moneyは補充の支払いを引いた所持金、state.scoreは支払い前なので探索の根ではこちらを使う
+    pub fn choose_use_card(&mut self, input: &Input, t: usize, x: &[i64], money: i64) -> Action {
+        match self.policy {
+            Policy::Greedy => self.select_use_card(t),
+            Policy::Expectimax(search) => search.select_use_card(self, input, t, x, money),
+        }
+    }
+
+    // 補充するカードは方策によらず貪欲に選ぶ、終盤はモンテカルロ
+    // 探索の葉の評価はeval_refillより粗いので、補充の候補を直接比べると貪欲に負ける
+    // （seed 100-199で、根の補充も探索するとdepth 2: -0.230、depth 3: -0.018）
+    pub fn choose_refill(&self, input: &Input, new_cards: &[Offer], t: usize, x: &[i64]) -> usize {
+        self.select_refill(input, new_cards, t, x)
+    }
+
+    // 終盤はモンテカルロで補充するカードを決める
+    // xはこれまでに見た補充の候補の種類ごとの出現回数
+    pub fn select_refill(&self, input: &Input, new_cards: &[Offer], t: usize, x: &[i64]) -> usize {
+        const MONTE_CARLO_ROUND: usize = 100;
+        if t < MONTE_CARLO_TURN {
+            self.select_new_card(new_cards, t)
+        } else if t < input.t - 1 {
+            (0..new_cards.len())
+                .max_by_key(|&i| {
+                    if new_cards[i].price <= self.state.score {
+                        montecarlo(MONTE_CARLO_ROUND, self, input, t, x, true, i, new_cards)
+                    } else {
+                        -1
+                    }
+                })
+                .unwrap()
+        } else {
+            0
+        }
+    }
+
+    pub fn eval(&self, card: &Card, t: usize) -> (f64, usize) {
+        match card {
+            Card::Invest => match self.invest_rule(t) {
+                Some(_) => (INF, 0),
+                None => (-INF, 0),
+            },
+            // 学習済みのモデルは補充の選択だけで学習しているので、使う時は手調整の評価値のまま
+            _ => self.valuer(t).use_value(card),
         }
     }
 
@@ -216,72 +250,120 @@ impl Solver {
         }
 
         match card {
-            Card::Invest => {
-                if self.state.invest_level >= MAX_INVEST_LEVEL || t >= self.param.invest_limit {
-                    return -INF;
-                }
-                let invest_card_count = self
-                    .state
-                    .cards
-                    .iter()
-                    .filter(|&&card| card == Card::Invest)
-                    .count();
-                if (self.state.score as f64 >= p as f64 * 1.5
-                    && p / 2_i64.pow(self.state.invest_level as u32) < self.param.invest_cost)
-                    || invest_card_count == self.state.cards.len() - 1
-                    || self.state.invest_level == MAX_INVEST_LEVEL - 1
-                {
-                    INF
-                } else {
-                    -INF
+            Card::Invest => match self.refill_invest_rule(p, t) {
+                Some(_) => INF,
+                None => -INF,
+            },
+            _ => {
+                let valuer = self.valuer(t);
+                let value = valuer.hold_value(card, p);
+                if value <= -INF {
+                    return value;
                 }
+                let value = self.learned_value(&valuer, card, p, t, value);
+                // 手札の他のカードと役割が重なる分を引く
+                value
+                    + self.param.hand_weight.at(t)
+                        * hand_bonus(&valuer, &self.state.cards, card, self.param.hand_decay)
+            }
+        }
+    }
+
+    // 増資カードを使う条件のうち、最初に当てはまったものの番号
+    // 1. 手持ちのカードが全て増資になった場合
+    // 2. 増資の期限が来た場合
+    // 3. 前回増資した場合（増資カードを消費している場合）
+    // 4. 増資回数がMAX_INVEST_LEVELに到達する場合
+    pub fn invest_rule(&self, t: usize) -> Option<usize> {
+        if self.state.invest_level >= MAX_INVEST_LEVEL {
+            return None;
+        }
+        let invest_card_count = self.invest_card_count();
+        [
+            self.state.cards.len() == invest_card_count,
+            t >= self.param.invest_limit,
+            self.state.last_invest_round + 1 == t,
+            invest_card_count + self.state.invest_level == MAX_INVEST_LEVEL,
+        ]
+        .iter()
+        .position(|&fired| fired)
+        .map(|i| i + 1)
+    }
+
+    // 増資カードを補充する条件のうち、最初に当てはまったものの番号
+    // 1. 所持金に余裕があり、増資レベルに対して安い場合
+    // 2. 補充すると手持ちのカードが全て増資になる場合
+    // 3. 次の増資で最大レベルに到達する場合
+    pub fn refill_invest_rule(&self, p: i64, t: usize) -> Option<usize> {
+        if p > self.state.score
+            || self.state.invest_level >= MAX_INVEST_LEVEL
+            || t >= self.param.invest_limit
+        {
+            return None;
+        }
+        [
+            self.state.score as f64 >= p as f64 * 1.5
+                && p / 2_i64.pow(self.state.invest_level as u32) < self.param.invest_cost,
+            self.invest_card_count() == self.state.cards.len() - 1,
+            self.state.invest_level == MAX_INVEST_LEVEL - 1,
+        ]
+        .iter()
+        .position(|&fired| fired)
+        .map(|i| i + 1)
+    }
+
+    fn invest_card_count(&self) -> usize {
+        self.state
+            .cards
+            .iter()
+            .filter(|&&card| card == Card::Invest)
+            .count()
+    }
+
+    pub fn valuer(&self, t: usize) -> CardValuer<'_> {
+        CardValuer::new(
+            &self.state,
+            &self.param,
+            &self.project_model,
+            StockPlan::new(&self.state, &self.param, t),
+            t,
+        )
+    }
+
+    // 学習済みの評価関数を使う場合は、手調整の評価値を特徴量の一つとして置き換える
+    fn learned_value(&self, valuer: &CardValuer, card: &Card, p: i64, t: usize, value: f64) -> f64 {
+        match &self.evaluator {
+            Evaluator::Learned(model) if value > -INF => {
+                let scale = (1_i64 << self.state.invest_level) as f64;
+                model.predict(&features(valuer, &self.state, card, p, t, value)) * scale
             }
-            _ => self.eval(card, p, t, true).0,
+            _ => value,
         }
     }
 
-    pub fn select_new_card(&self, new_cards: &Vec<(Card, i64)>, t: usize) -> usize {
+    pub fn select_new_card(&self, new_cards: &[Offer], t: usize) -> usize {
         let eval_refills: Vec<f64> = new_cards
             .iter()
-            .map(|(card, p)| self.eval_refill(&card, *p, t))
+            .map(|offer| self.eval_refill(&offer.card, offer.price, t))
             .collect();
         let mut card_idx = (0..new_cards.len()).collect::<Vec<usize>>();
         card_idx.sort_by(|i, j| eval_refills[*j].partial_cmp(&eval_refills[*i]).unwrap());
 
-        // if cfg!(feature = "local") {
-        //     println!("# eval_refill, m, card_type, p");
-        //     for &i in card_idx.iter() {
-        //         println!(
-        //             "# {} {:.3} {:?}, {}",
-        //             i, eval_refills[i], new_cards[i].0, new_cards[i].1
-        //         );
-        //     }
-        // }
-
         card_idx[0]
     }
 
-    pub fn select_use_card(&self, t: usize) -> (usize, usize) {
+    pub fn select_use_card(&self, t: usize) -> Action {
         let evals: Vec<(f64, usize)> = self
             .state
             .cards
             .iter()
-            .map(|card| self.eval(&card, 0, t, false))
+            .map(|card| self.eval(card, t))
             .collect();
 
         let mut card_idx = (0..self.state.cards.len()).collect::<Vec<usize>>();
         card_idx.sort_by(|i, j| evals[*j].partial_cmp(&evals[*i]).unwrap());
 
-        // if cfg!(feature = "local") {
-        //     println!("# eval, m, card_type, p");
-        //     for &i in card_idx.iter() {
-        //         println!(
-        //             "# {} {:.3} {} {:?}",
-        //             i, evals[i].0, evals[i].1, self.state.cards[i]
-        //         );
-        //     }
-        // }
-
-        (card_idx[0], evals[card_idx[0]].1)
+        let c = card_idx[0];
+        Action::use_card(c, self.state.cards[c], evals[c].1)
     }
 }
@@ -0,0 +1,48 @@
+use crate::def::*;
+
+// 増資の前に貯めておくプロジェクトの計画
+// 増資するとプロジェクトのhはそのままで労働が倍になるので、hが大きくv/hの良いプロジェクトは増資の後に終わらせる
+// 増資カードを持っている間だけ貯め、それ以外のプロジェクトは今終わらせる
+// holdは貯める枠のビット
+// プロジェクトのhは増資レベル0で256以下なので、stockpile_hがそれより大きければ貯めない
+// 既定値の1000では貯めない（seed 100-299で200: -0.029、128: -0.258）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StockPlan {
+    pub hold: u32,
+}
+
+impl StockPlan {
+    pub fn new(state: &State, param: &Param, t: usize) -> StockPlan {
+        let mut plan = StockPlan::default();
+        if state.invest_level >= MAX_INVEST_LEVEL
+            || t >= param.invest_limit
+            || !state.cards.contains(&Card::Invest)
+        {
+            return plan;
+        }
+        let scale = 1_i64 << state.invest_level;
+        for (m, p) in state.projects.iter().enumerate() {
+            if p.h >= param.stockpile_h * scale && p.v >= p.h {
+                plan.hold |= 1 << m;
+            }
+        }
+        // 全て貯めると労働の使い道がなくなるので、hが最小のものは終わらせる
+        if plan.hold.count_ones() as usize == state.projects.len() {
+            let m = (0..state.projects.len())
+                .min_by_key(|&m| state.projects[m].h)
+                .unwrap();
+            plan.hold &= !(1 << m);
+        }
+        plan
+    }
+
+    pub fn is_held(&self, m: usize) -> bool {
+        self.hold >> m & 1 == 1
+    }
+
+    pub fn held(&self) -> Vec<usize> {
+        (0..u32::BITS as usize)
+            .filter(|&m| self.is_held(m))
+            .collect()
+    }
+}
@@ -0,0 +1,334 @@
+use std::io::Write;
+
+use crate::def::*;
+use crate::expectimax::*;
+use crate::param_table::*;
+use crate::project_model::*;
+use crate::simulator::*;
+use crate::solver::*;
+
+// 1ターン分の記録、状態はカードを使う前のもの
+#[derive(Debug, Clone)]
+pub struct TurnRecord {
+    pub t: usize,
+    pub invest_level: usize,
+    pub money: i64,
+    pub cards: Vec<Card>,
+    pub projects: Vec<Project>,
+    pub action: Action,
+    pub offers: Vec<Offer>,
+    pub refill: usize,
+    pub comments: Vec<String>,
+}
+
+// ゲーム全体の記録
+//
+// 1行目: n m k t
+// 各ターン: t invest_level money | 手札 | プロジェクト | c m | 補充の候補 | r
+// そのターンのコメント: # <コメント>（0行以上）
+// 最終行: score <最終スコア> <最終の増資レベル>
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub n: usize,
+    pub m: usize,
+    pub k: usize,
+    pub t: usize,
+    pub turns: Vec<TurnRecord>,
+    pub score: i64,
+    pub invest_level: usize,
+}
+
+impl Trace {
+    pub fn new(input: &Input) -> Trace {
+        Trace {
+            n: input.n,
+            m: input.m,
+            k: input.k,
+            t: input.t,
+            ..Default::default()
+        }
+    }
+
+    pub fn input(&self) -> Input {
+        Input {
+            n: self.n,
+            m: self.m,
+            k: self.k,
+            t: self.t,
+        }
+    }
+
+    // トレースのファイルか、seedならローカルのシミュレータで解いたトレース
+    // 解く場合は選んだ行動のコメントも記録する
+    pub fn from_source(source: &str, param_table: &ParamTable, evaluator: &Evaluator) -> Trace {
+        match source.parse::<u64>() {
+            Ok(seed) => {
+                let game = Game::generate(seed);
+                let mut solver = Solver {
+                    state: game.initial_state(),
+                    param: param_table.lookup(&game.input),
+                    project_model: ProjectModel::new(),
+                    evaluator: evaluator.clone(),
+                    verbosity: 1,
+                    policy: Policy::Greedy,
+                };
+                solver.solve(&game.input, &mut MockInteractor::from_game(&game))
+            }
+            Err(_) => Trace::load(source).unwrap(),
+        }
+    }
+
+    pub fn write(&self, path: &str) {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+        writeln!(file, "{} {} {} {}", self.n, self.m, self.k, self.t).unwrap();
+        for turn in self.turns.iter() {
+            writeln!(
+                file,
+                "{} {} {} | {} | {} | {} | {} | {}",
+                turn.t,
+                turn.invest_level,
+                turn.money,
+                format_cards(&turn.cards),
+                format_projects(&turn.projects),
+                turn.action,
+                format_offers(&turn.offers),
+                turn.refill,
+            )
+            .unwrap();
+            for comment in turn.comments.iter() {
+                writeln!(file, "# {}", comment).unwrap();
+            }
+        }
+        writeln!(file, "score {} {}", self.score, self.invest_level).unwrap();
+    }
+
+    pub fn load(path: &str) -> Result<Trace, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Trace::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Trace, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = parse_numbers::<usize>(lines.next().ok_or("empty trace")?)?;
+        if header.len() != 4 {
+            return Err(format!("invalid header: {:?}", header));
+        }
+        let mut trace = Trace {
+            n: header[0],
+            m: header[1],
+            k: header[2],
+            t: header[3],
+            ..Default::default()
+        };
+
+        for line in lines {
+            if let Some(comment) = line.strip_prefix('#') {
+                let turn = trace
+                    .turns
+                    .last_mut()
+                    .ok_or("comment before the first turn")?;
+                turn.comments.push(comment.trim().to_string());
+                continue;
+            }
+            if let Some(score) = line.strip_prefix("score ") {
+                match parse_numbers::<i64>(score)?[..] {
+                    [score, invest_level] => {
+                        trace.score = score;
+                        trace.invest_level = invest_level as usize;
+                    }
+                    _ => return Err(format!("invalid score: `{line}`")),
+                }
+                continue;
+            }
+            let fields: Vec<&str> = line.split('|').collect();
+            if fields.len() != 6 {
+                return Err(format!("invalid turn: `{line}`"));
+            }
+            let (t, invest_level, money) = match parse_numbers::<i64>(fields[0])?[..] {
+                [t, invest_level, money] => (t as usize, invest_level as usize, money),
+                _ => return Err(format!("invalid turn header: `{}`", fields[0])),
+            };
+            let cards = parse_cards(fields[1])?;
+            let refill = match parse_numbers::<usize>(fields[5])?[..] {
+                [refill] => refill,
+                _ => return Err(format!("invalid refill: `{}`", fields[5])),
+            };
+            let action = match parse_numbers::<usize>(fields[3])?[..] {
+                [c, m] => Action::parse_use(c, m, &cards)?,
+                _ => return Err(format!("invalid action: `{}`", fields[3])),
+            };
+            trace.turns.push(TurnRecord {
+                t,
+                invest_level,
+                money,
+                cards,
+                projects: parse_projects(fields[2])?,
+                action,
+                offers: parse_offers(fields[4])?,
+                refill,
+                comments: vec![],
+            });
+        }
+        Ok(trace)
+    }
+}
+
+pub fn card_w(card: &Card) -> i64 {
+    match card {
+        Card::WorkSingle(w) | Card::WorkAll(w) => *w,
+        _ => 0,
+    }
+}
+
+// 要素は空白、値は:で区切る、空きは-
+pub fn format_cards(cards: &[Card]) -> String {
+    cards
+        .iter()
+        .map(|card| match card {
+            Card::None => "-".to_string(),
+            _ => format!("{}:{}", card.to_t(), card_w(card)),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn format_projects(projects: &[Project]) -> String {
+    projects
+        .iter()
+        .map(|p| format!("{}:{}", p.h, p.v))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn format_offers(offers: &[Offer]) -> String {
+    offers
+        .iter()
+        .map(|o| format!("{}:{}:{}", o.card.to_t(), card_w(&o.card), o.price))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn parse_cards(s: &str) -> Result<Vec<Card>, String> {
+    s.split_whitespace()
+        .map(|token| {
+            if token == "-" {
+                return Ok(Card::None);
+            }
+            let x = parse_numbers::<i64>(&token.replace(':', " "))?;
+            parse_card(&x, token)
+        })
+        .collect()
+}
+
+pub fn parse_projects(s: &str) -> Result<Vec<Project>, String> {
+    s.split_whitespace()
+        .map(|token| {
+            let x = parse_numbers::<i64>(&token.replace(':', " "))?;
+            match x[..] {
+                [h, v] => Ok(Project { h, v }),
+                _ => Err(format!("invalid project: `{token}`")),
+            }
+        })
+        .collect()
+}
+
+pub fn parse_offers(s: &str) -> Result<Vec<Offer>, String> {
+    s.split_whitespace()
+        .map(|token| {
+            let x = parse_numbers::<i64>(&token.replace(':', " "))?;
+            match x[..] {
+                [t, w, price] => Ok(Offer {
+                    card: parse_card(&[t, w], token)?,
+                    price,
+                }),
+                _ => Err(format!("invalid offer: `{token}`")),
+            }
+        })
+        .collect()
+}
+
+fn parse_card(x: &[i64], token: &str) -> Result<Card, String> {
+    match x {
+        &[t, w] if (0..=4).contains(&t) => Ok(Card::from_tw(t as usize, w)),
+        _ => Err(format!("invalid card: `{token}`")),
+    }
+}
+
+fn parse_numbers<T: std::str::FromStr>(s: &str) -> Result<Vec<T>, String> {
+    s.split_whitespace()
+        .map(|x| x.parse::<T>().map_err(|_| format!("invalid number: `{x}`")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "2 2 2 2
+0 0 0 | 0:1 0:1 | 1:5 3:7 | 0 0 | 0:1:0 4:0:300 | 0
+# t=0 use 0 WorkSingle(1) on 0
+1 0 5 | 0:1 0:1 | 2:2 3:7 | 1 1 | 0:1:0 2:0:5 | 1
+score 0 0
+";
+
+    fn write_and_load(trace: &Trace) -> Trace {
+        let path = std::env::temp_dir().join(format!("ahc029_trace_{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+        trace.write(path);
+        let loaded = Trace::load(path);
+        std::fs::remove_file(path).unwrap();
+        loaded.unwrap()
+    }
+
+    #[test]
+    fn parse_and_write_trace() {
+        let trace = Trace::parse(TRACE).unwrap();
+        assert_eq!(trace.turns.len(), 2);
+        let turn = &trace.turns[1];
+        assert_eq!((turn.t, turn.invest_level, turn.money), (1, 0, 5));
+        assert_eq!(
+            turn.action,
+            Action::UseSingle {
+                slot: 1,
+                project: 1
+            }
+        );
+        assert_eq!(turn.offers[1].card, Card::CancelSingle);
+        assert_eq!(turn.refill, 1);
+        assert_eq!(
+            trace.turns[0].comments,
+            vec!["t=0 use 0 WorkSingle(1) on 0"]
+        );
+
+        let loaded = write_and_load(&trace);
+        assert_eq!(
+            (loaded.score, loaded.invest_level),
+            (trace.score, trace.invest_level)
+        );
+        for (a, b) in trace.turns.iter().zip(loaded.turns.iter()) {
+            assert_eq!(
+                (a.t, a.invest_level, a.money),
+                (b.t, b.invest_level, b.money)
+            );
+            assert_eq!(a.cards, b.cards);
+            assert_eq!(a.projects, b.projects);
+            assert_eq!(a.action, b.action);
+            assert_eq!(a.offers, b.offers);
+            assert_eq!(a.refill, b.refill);
+            assert_eq!(a.comments, b.comments);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_trace() {
+        assert!(Trace::parse("").is_err());
+        assert!(Trace::parse("2 2 2").is_err());
+        assert!(Trace::parse("2 2 2 2\n# comment\n").is_err());
+        assert!(Trace::parse(&TRACE.replace("score 0 0", "score 0")).is_err());
+        assert!(Trace::parse(&TRACE.replace("| 1\n", "| 1 | 0\n")).is_err());
+        assert!(Trace::parse(&TRACE.replace("1 0 5 |", "1 0 |")).is_err());
+        assert!(Trace::parse(&TRACE.replace("| 1\n", "|\n")).is_err());
+        assert!(Trace::parse(&TRACE.replace("| 1 1 |", "| 1 |")).is_err());
+        assert!(Trace::parse(&TRACE.replace("0:1 0:1 | 2:2", "0:1 7:1 | 2:2")).is_err());
+    }
+}
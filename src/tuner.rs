@@ -0,0 +1,421 @@
+use std::io::Write;
+
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+use rand_distr::StandardNormal;
+
+use crate::def::*;
+use crate::expectimax::*;
+use crate::param_table::*;
+use crate::project_model::*;
+use crate::simulator::*;
+use crate::solver::*;
+
+// 探索範囲、Param::NAMESと同じ順番
+// スケジュールのパラメータは各ノットで同じ範囲を使う
+// plan_horizonは2以上だと1局に数秒かかるので1までにする
+const BOUNDS: [(f64, f64); 11] = [
+    (0., 5.),
+    (0., 2.),
+    (0., 2.),
+    (700., 1000.),
+    (800., 1000.),
+    (100., 1000.),
+    (0.5, 5.),
+    (0., 1.),
+    (0., 1.),
+    (10., 1000.),
+    (0., 1.),
+];
+
+// bucketに当てはまるゲームを探すseedの数の上限
+// 一番狭いbucketでも1/168の割合で当てはまるので、通常は届かない
+const SCAN_LIMIT: u64 = 100_000;
+
+// sep-CMA-ESでParamを最適化する
+// 各候補はseed_offset以降でbucketに当てはまるseeds個のゲームの log(score) の平均で評価する
+// bucketごとに別のcheckpointを使って、結果をパラメータテーブルの区間として貼り付ける
+// usage: tune <checkpoint> [generations] [seeds] [seed_offset] [bucket]
+pub fn run(args: &[String], param_table: &ParamTable, evaluator: &Evaluator) {
+    let path = args.first().map(|s| s.as_str()).unwrap_or("tune.ckpt");
+    let generations = args.get(1).map(|s| s.parse().unwrap()).unwrap_or(50);
+    let seeds = args.get(2).map(|s| s.parse().unwrap()).unwrap_or(100);
+    let seed_offset = args.get(3).map(|s| s.parse().unwrap()).unwrap_or(0);
+    let bucket = match args.get(4).map(|s| Bucket::parse(s)) {
+        Some(Ok(bucket)) => bucket,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        None => Bucket::ALL,
+    };
+
+    let games: Vec<Game> = (seed_offset..seed_offset + SCAN_LIMIT)
+        .map(Game::generate)
+        .filter(|game| bucket.contains(&game.input))
+        .take(seeds)
+        .collect();
+    if games.is_empty() {
+        eprintln!("no game in {bucket} within {SCAN_LIMIT} seeds from {seed_offset}");
+        std::process::exit(1);
+    }
+    if games.len() < seeds {
+        eprintln!(
+            "only {} games in {bucket} within {SCAN_LIMIT} seeds",
+            games.len()
+        );
+    }
+    let param = param_table.lookup_for(&bucket);
+    let mut cma = match CmaEs::load(path, bounds().len()) {
+        Ok(Some(cma)) => {
+            eprintln!("resume from {} (generation {})", path, cma.generation);
+            cma
+        }
+        Ok(None) => CmaEs::new(&normalize(&param.to_vec())),
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            std::process::exit(1);
+        }
+    };
+    if cma.best.is_empty() {
+        let x = cma.mean.clone();
+        cma.best_score = evaluate(&Param::from_vec(&denormalize(&x)), evaluator, &games);
+        cma.best = x;
+    }
+
+    let mut rng = ChaCha20Rng::seed_from_u64(cma.generation as u64);
+    while cma.generation < generations {
+        let candidates = cma.sample(&mut rng);
+        let scores: Vec<f64> = std::thread::scope(|s| {
+            let handles: Vec<_> = candidates
+                .iter()
+                .map(|x| {
+                    let param = Param::from_vec(&denormalize(x));
+                    let games = &games;
+                    s.spawn(move || evaluate(&param, evaluator, games))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        cma.update(&candidates, &scores);
+        cma.save(path);
+
+        eprintln!(
+            "generation {}: best {:.4}, generation best {:.4}, sigma {:.4}",
+            cma.generation,
+            cma.best_score,
+            scores.iter().fold(f64::MIN, |a, &b| a.max(b)),
+            cma.sigma,
+        );
+    }
+
+    let best = Param::from_vec(&denormalize(&cma.best));
+    eprintln!("best score: {:.4}", cma.best_score);
+    let header = if bucket == Bucket::ALL {
+        "default".to_string()
+    } else {
+        bucket.to_string()
+    };
+    print!("{}", format_section(&header, &best));
+}
+
+fn evaluate(param: &Param, evaluator: &Evaluator, games: &[Game]) -> f64 {
+    let project_model = ProjectModel::new();
+    games
+        .iter()
+        .map(|game| {
+            let mut solver = Solver {
+                state: game.initial_state(),
+                param: *param,
+                project_model,
+                evaluator: evaluator.clone(),
+                verbosity: 0,
+                policy: Policy::Greedy,
+            };
+            (play_game(game, &mut solver).max(1) as f64).ln()
+        })
+        .sum::<f64>()
+        / games.len() as f64
+}
+
+fn bounds() -> Vec<(f64, f64)> {
+    BOUNDS
+        .iter()
+        .zip(Param::DIMS)
+        .flat_map(|(&bound, dim)| vec![bound; dim])
+        .collect()
+}
+
+// 探索は各パラメータを[0, 1]に正規化した空間で行う
+fn normalize(x: &[f64]) -> Vec<f64> {
+    x.iter()
+        .zip(bounds().iter())
+        .map(|(x, (lo, hi))| ((x - lo) / (hi - lo)).clamp(0., 1.))
+        .collect()
+}
+
+fn denormalize(x: &[f64]) -> Vec<f64> {
+    x.iter()
+        .zip(bounds().iter())
+        .map(|(x, (lo, hi))| lo + x.clamp(0., 1.) * (hi - lo))
+        .collect()
+}
+
+// 共分散行列を対角に制限したCMA-ES (Ros & Hansen, 2008)
+struct CmaEs {
+    generation: usize,
+    sigma: f64,
+    mean: Vec<f64>,
+    c: Vec<f64>,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    best_score: f64,
+    best: Vec<f64>,
+}
+
+impl CmaEs {
+    fn new(mean: &[f64]) -> CmaEs {
+        let n = mean.len();
+        CmaEs {
+            generation: 0,
+            sigma: 0.2,
+            mean: mean.to_vec(),
+            c: vec![1.; n],
+            pc: vec![0.; n],
+            ps: vec![0.; n],
+            best_score: f64::MIN,
+            best: vec![],
+        }
+    }
+
+    fn lambda(&self) -> usize {
+        4 + (3. * (self.mean.len() as f64).ln()) as usize
+    }
+
+    fn weights(&self) -> Vec<f64> {
+        let mu = self.lambda() / 2;
+        let w: Vec<f64> = (0..mu)
+            .map(|i| (mu as f64 + 0.5).ln() - (i as f64 + 1.).ln())
+            .collect();
+        let sum = w.iter().sum::<f64>();
+        w.iter().map(|w| w / sum).collect()
+    }
+
+    // 範囲外の候補は範囲内に戻してから評価・更新に使う
+    fn sample(&self, rng: &mut ChaCha20Rng) -> Vec<Vec<f64>> {
+        (0..self.lambda())
+            .map(|_| {
+                self.mean
+                    .iter()
+                    .zip(self.c.iter())
+                    .map(|(m, c)| {
+                        let z: f64 = rng.sample(StandardNormal);
+                        (m + self.sigma * c.sqrt() * z).clamp(0., 1.)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[allow(clippy::needless_range_loop)]
+    fn update(&mut self, candidates: &[Vec<f64>], scores: &[f64]) {
+        let n = self.mean.len() as f64;
+        let weights = self.weights();
+        let mueff = 1. / weights.iter().map(|w| w * w).sum::<f64>();
+        let cc = 4. / (n + 4.);
+        let cs = (mueff + 2.) / (n + mueff + 3.);
+        let c1 = 2. / ((n + 1.3).powi(2) + mueff) * (n + 2.) / 3.;
+        let cmu = (2. * (mueff - 2. + 1. / mueff) / ((n + 2.).powi(2) + mueff) * (n + 2.) / 3.)
+            .min(1. - c1);
+        let damps = 1. + 2. * (((mueff - 1.) / (n + 1.)).sqrt() - 1.).max(0.) + cs;
+        let chi_n = n.sqrt() * (1. - 1. / (4. * n) + 1. / (21. * n * n));
+
+        let mut order: Vec<usize> = (0..candidates.len()).collect();
+        order.sort_by(|&i, &j| scores[j].partial_cmp(&scores[i]).unwrap());
+        if scores[order[0]] > self.best_score {
+            self.best_score = scores[order[0]];
+            self.best = candidates[order[0]].clone();
+        }
+
+        let ys: Vec<Vec<f64>> = order
+            .iter()
+            .take(weights.len())
+            .map(|&i| {
+                candidates[i]
+                    .iter()
+                    .zip(self.mean.iter())
+                    .map(|(x, m)| (x - m) / self.sigma)
+                    .collect()
+            })
+            .collect();
+        let yw: Vec<f64> = (0..self.mean.len())
+            .map(|j| weights.iter().zip(ys.iter()).map(|(w, y)| w * y[j]).sum())
+            .collect();
+
+        for j in 0..self.mean.len() {
+            self.mean[j] += self.sigma * yw[j];
+            self.ps[j] =
+                (1. - cs) * self.ps[j] + (cs * (2. - cs) * mueff).sqrt() * yw[j] / self.c[j].sqrt();
+        }
+        let ps_norm = self.ps.iter().map(|p| p * p).sum::<f64>().sqrt();
+        let hsig = ps_norm / (1. - (1. - cs).powi(2 * (self.generation as i32 + 1))).sqrt() / chi_n
+            < 1.4 + 2. / (n + 1.);
+        let hsig = if hsig { 1. } else { 0. };
+        for j in 0..self.mean.len() {
+            self.pc[j] = (1. - cc) * self.pc[j] + hsig * (cc * (2. - cc) * mueff).sqrt() * yw[j];
+            let rank_mu = weights
+                .iter()
+                .zip(ys.iter())
+                .map(|(w, y)| w * y[j] * y[j])
+                .sum::<f64>();
+            self.c[j] = (1. - c1 - cmu) * self.c[j]
+                + c1 * (self.pc[j] * self.pc[j] + (1. - hsig) * cc * (2. - cc) * self.c[j])
+                + cmu * rank_mu;
+        }
+        self.sigma *= ((cs / damps) * (ps_norm / chi_n - 1.)).exp();
+        self.generation += 1;
+    }
+
+    fn save(&self, path: &str) {
+        let join = |x: &[f64]| {
+            x.iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        let mut file = std::fs::File::create(path).unwrap();
+        writeln!(file, "generation {}", self.generation).unwrap();
+        writeln!(file, "sigma {}", self.sigma).unwrap();
+        writeln!(file, "mean {}", join(&self.mean)).unwrap();
+        writeln!(file, "c {}", join(&self.c)).unwrap();
+        writeln!(file, "pc {}", join(&self.pc)).unwrap();
+        writeln!(file, "ps {}", join(&self.ps)).unwrap();
+        writeln!(file, "best_score {}", self.best_score).unwrap();
+        writeln!(file, "best {}", join(&self.best)).unwrap();
+        let best = Param::from_vec(&denormalize(&self.best));
+        for line in format_section("best", &best).lines() {
+            writeln!(file, "# {line}").unwrap();
+        }
+    }
+
+    // ファイルがなければNone、dimはパラメータを並べた次元
+    // パラメータを追加する前の古いcheckpointは次元が合わないのでエラーにする
+    fn load(path: &str, dim: usize) -> Result<Option<CmaEs>, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut cma = CmaEs::new(&[]);
+        for line in text.lines() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let values = value
+                .split_whitespace()
+                .map(|x| x.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| format!("invalid values for `{key}`: `{value}`"))?;
+            let scalar = || match values[..] {
+                [x] => Ok(x),
+                _ => Err(format!("`{key}` expects one value, found {}", values.len())),
+            };
+            match key {
+                "generation" => cma.generation = scalar()? as usize,
+                "sigma" => cma.sigma = scalar()?,
+                "best_score" => cma.best_score = scalar()?,
+                "mean" => cma.mean = values,
+                "c" => cma.c = values,
+                "pc" => cma.pc = values,
+                "ps" => cma.ps = values,
+                "best" => cma.best = values,
+                _ => return Err(format!("unknown key in checkpoint: `{key}`")),
+            }
+        }
+        for (key, len) in [
+            ("mean", cma.mean.len()),
+            ("c", cma.c.len()),
+            ("pc", cma.pc.len()),
+            ("ps", cma.ps.len()),
+        ] {
+            if len != dim {
+                return Err(format!(
+                    "stale checkpoint: `{key}` has {len} values but the tuner has {dim} parameters"
+                ));
+            }
+        }
+        if !cma.best.is_empty() && cma.best.len() != dim {
+            return Err(format!(
+                "stale checkpoint: `best` has {} values but the tuner has {dim} parameters",
+                cma.best.len()
+            ));
+        }
+        Ok(Some(cma))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ahc029_{}_{}", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    // 1世代進めたもの、保存するのは評価した後だけ
+    fn one_generation(dim: usize) -> CmaEs {
+        let mut cma = CmaEs::new(&vec![0.5; dim]);
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let candidates = cma.sample(&mut rng);
+        let scores: Vec<f64> = candidates.iter().map(|x| -x[0]).collect();
+        cma.update(&candidates, &scores);
+        cma
+    }
+
+    #[test]
+    fn round_trip_checkpoint() {
+        let dim = bounds().len();
+        let cma = one_generation(dim);
+        let path = temp_path("checkpoint_round_trip");
+        cma.save(&path);
+        let loaded = CmaEs::load(&path, dim);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap().unwrap();
+        assert_eq!(loaded.generation, 1);
+        assert_eq!(loaded.sigma, cma.sigma);
+        assert_eq!(loaded.mean, cma.mean);
+        assert_eq!(loaded.c, cma.c);
+        assert_eq!(loaded.pc, cma.pc);
+        assert_eq!(loaded.ps, cma.ps);
+        assert_eq!(loaded.best_score, cma.best_score);
+        assert_eq!(loaded.best, cma.best);
+    }
+
+    #[test]
+    fn missing_checkpoint_starts_fresh() {
+        assert!(CmaEs::load(&temp_path("checkpoint_missing"), 3)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn reject_invalid_checkpoint() {
+        let dim = bounds().len();
+        let path = temp_path("checkpoint_invalid");
+        one_generation(dim).save(&path);
+        let text = std::fs::read_to_string(&path).unwrap();
+        let stale = CmaEs::load(&path, dim + 1);
+        for bad in [
+            text.replace("sigma ", "sigma 0.1 "),
+            text.replace("sigma ", "sigma x"),
+            text.replace("generation ", "gen "),
+        ] {
+            std::fs::write(&path, bad).unwrap();
+            assert!(CmaEs::load(&path, dim).is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(stale.is_err());
+    }
+}
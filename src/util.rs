@@ -27,7 +27,7 @@ pub mod rnd {
         (next() % (high - low)) + low
     }
 
-    pub fn shuffle<I>(vec: &mut Vec<I>) {
+    pub fn shuffle<I>(vec: &mut [I]) {
         for i in 0..vec.len() {
             let j = gen_range(0, vec.len());
             vec.swap(i, j);
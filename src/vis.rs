@@ -0,0 +1,247 @@
+use std::io::Write;
+
+use crate::def::*;
+use crate::param_table::*;
+use crate::solver::*;
+use crate::trace::*;
+
+const WIDTH: f64 = 1000.;
+const HEIGHT: f64 = 300.;
+const MARGIN: f64 = 40.;
+
+// トレースを1つのHTMLファイルにする
+// 所持金の推移（対数軸）と増資のタイミング、ターンごとの手札・プロジェクト・補充の候補を表示する
+// <trace>の代わりにseedを渡すと、ローカルのシミュレータで解いたトレースを使う
+// usage: vis <trace|seed> [out.html]
+pub fn run(args: &[String], param_table: &ParamTable, evaluator: &Evaluator) {
+    let source = args.first().map(|s| s.as_str()).unwrap_or("trace.log");
+    let out = args.get(1).map(|s| s.as_str()).unwrap_or("vis.html");
+
+    let trace = Trace::from_source(source, param_table, evaluator);
+
+    let mut file = std::fs::File::create(out).unwrap();
+    write!(file, "{}", render(&trace)).unwrap();
+    eprintln!("wrote {} (score {})", out, trace.score);
+}
+
+pub fn render(trace: &Trace) -> String {
+    TEMPLATE
+        .replace("{{TITLE}}", &format!("score {}", trace.score))
+        .replace("{{SVG}}", &render_money_curve(trace))
+        .replace("{{DATA}}", &to_json(trace))
+}
+
+fn render_money_curve(trace: &Trace) -> String {
+    let max_log = trace
+        .turns
+        .iter()
+        .map(|turn| turn.money)
+        .chain([trace.score])
+        .map(|money| (money.max(0) as f64 + 1.).log10())
+        .fold(1., f64::max)
+        .ceil();
+    let x = |t: usize| MARGIN + (WIDTH - 2. * MARGIN) * t as f64 / trace.t.max(1) as f64;
+    let y = |money: i64| {
+        HEIGHT - MARGIN - (HEIGHT - 2. * MARGIN) * (money.max(0) as f64 + 1.).log10() / max_log
+    };
+
+    let mut svg = format!(
+        r##"<svg id="curve" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}">"##
+    );
+    for e in 0..=max_log as usize {
+        let money = 10_i64.pow(e as u32) - 1;
+        svg += &format!(
+            r##"<line x1="{}" x2="{}" y1="{y}" y2="{y}" stroke="#ddd"/><text x="2" y="{y}" font-size="10">1e{e}</text>"##,
+            MARGIN,
+            WIDTH - MARGIN,
+            y = y(money),
+        );
+    }
+    for turn in trace.turns.iter() {
+        if let Action::Invest { .. } = turn.action {
+            svg += &format!(
+                r##"<line x1="{x}" x2="{x}" y1="{}" y2="{}" stroke="#36c" stroke-width="1"><title>invest t={} L={}</title></line>"##,
+                MARGIN,
+                HEIGHT - MARGIN,
+                turn.t,
+                turn.invest_level + 1,
+                x = x(turn.t),
+            );
+        }
+    }
+    let points = trace
+        .turns
+        .iter()
+        .map(|turn| (turn.t, turn.money))
+        .chain([(trace.t, trace.score)])
+        .map(|(t, money)| format!("{:.1},{:.1}", x(t), y(money)))
+        .collect::<Vec<String>>()
+        .join(" ");
+    svg +=
+        &format!(r##"<polyline points="{points}" fill="none" stroke="#c33" stroke-width="1.5"/>"##);
+    svg += &format!(
+        r##"<line id="cursor" x1="{MARGIN}" x2="{MARGIN}" y1="{MARGIN}" y2="{}" stroke="#000" stroke-dasharray="4"/>"##,
+        HEIGHT - MARGIN
+    );
+    svg += &format!(
+        r##"<text x="{}" y="{}" font-size="10">t</text></svg>"##,
+        WIDTH - MARGIN,
+        HEIGHT - MARGIN / 2.
+    );
+    svg
+}
+
+// プロジェクトの進捗を出すために、同じプロジェクトが続いている間の最初のhを持っておく
+fn to_json(trace: &Trace) -> String {
+    let mut initial_h = vec![0; trace.m];
+    let mut turns = vec![];
+    let mut prev: Option<&TurnRecord> = None;
+    for turn in trace.turns.iter() {
+        for (i, proj) in turn.projects.iter().enumerate() {
+            let continued = prev.is_some_and(|prev| {
+                prev.projects[i].v == proj.v
+                    && prev.projects[i].h >= proj.h
+                    && prev.invest_level == turn.invest_level
+            });
+            if !continued {
+                initial_h[i] = proj.h;
+            }
+        }
+        let cards = turn
+            .cards
+            .iter()
+            .map(|card| format!("[{},{}]", card_t(card), card_w(card)))
+            .collect::<Vec<String>>()
+            .join(",");
+        let projects = turn
+            .projects
+            .iter()
+            .zip(initial_h.iter())
+            .map(|(proj, h0)| format!("[{},{},{}]", proj.h, proj.v, h0))
+            .collect::<Vec<String>>()
+            .join(",");
+        let offers = turn
+            .offers
+            .iter()
+            .map(|o| format!("[{},{},{}]", card_t(&o.card), card_w(&o.card), o.price))
+            .collect::<Vec<String>>()
+            .join(",");
+        let comments = turn
+            .comments
+            .iter()
+            .map(|comment| format!("\"{}\"", escape(comment)))
+            .collect::<Vec<String>>()
+            .join(",");
+        turns.push(format!(
+            r#"{{"t":{},"level":{},"money":{},"cards":[{}],"projects":[{}],"use":[{},{}],"offers":[{}],"refill":{},"comments":[{}]}}"#,
+            turn.t,
+            turn.invest_level,
+            turn.money,
+            cards,
+            projects,
+            turn.action.slot().unwrap(),
+            turn.action.project(),
+            offers,
+            turn.refill,
+            comments
+        ));
+        prev = Some(turn);
+    }
+    format!(
+        r#"{{"t":{},"score":{},"turns":[{}]}}"#,
+        trace.t,
+        trace.score,
+        turns.join(",")
+    )
+}
+
+// JSONの文字列とHTMLの両方で安全にする
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn card_t(card: &Card) -> i64 {
+    match card {
+        Card::None => -1,
+        _ => card.to_t() as i64,
+    }
+}
+
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+body { font-family: sans-serif; font-size: 13px; margin: 16px; }
+.row { display: flex; gap: 24px; align-items: flex-start; }
+.box { border: 1px solid #ccc; padding: 8px; min-width: 220px; }
+.card { display: inline-block; border: 1px solid #999; border-radius: 4px; padding: 4px 6px; margin: 2px; }
+.used { background: #fdd; border-color: #c33; }
+.chosen { background: #dfd; border-color: #3a3; }
+.target { outline: 2px solid #c33; }
+.bar { background: #eee; width: 200px; height: 10px; display: inline-block; vertical-align: middle; }
+.bar > div { background: #36c; height: 100%; }
+</style>
+</head>
+<body>
+<h3>{{TITLE}}</h3>
+{{SVG}}
+<div>
+<input id="slider" type="range" min="0" value="0" style="width: 920px">
+<button id="prev">&lt;</button><button id="next">&gt;</button>
+<span id="info"></span>
+</div>
+<div class="row">
+<div class="box"><b>hand</b><div id="hand"></div></div>
+<div class="box"><b>projects</b><div id="projects"></div></div>
+<div class="box"><b>offers</b><div id="offers"></div></div>
+</div>
+<div class="box"><b>comments</b><pre id="comments"></pre></div>
+<script>
+const data = {{DATA}};
+const names = ["Work", "WorkAll", "Cancel", "CancelAll", "Invest"];
+const cardName = (t, w) => t < 0 ? "-" : (t <= 1 ? names[t] + "(" + w + ")" : names[t]);
+const slider = document.getElementById("slider");
+slider.max = data.turns.length - 1;
+function show(i) {
+  const turn = data.turns[i];
+  slider.value = i;
+  document.getElementById("info").textContent =
+    "t=" + turn.t + " money=" + turn.money + " level=" + turn.level;
+  const x = 40 + 920 * turn.t / data.t;
+  const cursor = document.getElementById("cursor");
+  cursor.setAttribute("x1", x);
+  cursor.setAttribute("x2", x);
+  const usedT = turn.cards[turn.use[0]][0];
+  const single = usedT == 0 || usedT == 2;
+  document.getElementById("hand").innerHTML = turn.cards.map((c, j) =>
+    '<span class="card' + (j == turn.use[0] ? ' used' : '') + '">' + cardName(c[0], c[1]) + '</span>'
+  ).join("");
+  document.getElementById("projects").innerHTML = turn.projects.map((p, j) => {
+    const progress = p[2] > 0 ? 100 * (1 - p[0] / p[2]) : 0;
+    return '<div class="' + (single && j == turn.use[1] ? 'target' : '') + '">' +
+      'h=' + p[0] + ' v=' + p[1] + ' <span class="bar"><div style="width:' + progress + '%"></div></span></div>';
+  }).join("");
+  document.getElementById("offers").innerHTML = turn.offers.map((o, j) =>
+    '<span class="card' + (j == turn.refill ? ' chosen' : '') + '">' + cardName(o[0], o[1]) + ' p=' + o[2] + '</span>'
+  ).join("");
+  document.getElementById("comments").innerHTML = turn.comments.join("\n");
+}
+slider.oninput = () => show(+slider.value);
+document.getElementById("prev").onclick = () => show(Math.max(0, +slider.value - 1));
+document.getElementById("next").onclick = () => show(Math.min(data.turns.length - 1, +slider.value + 1));
+document.getElementById("curve").onclick = (e) => {
+  const r = e.currentTarget.getBoundingClientRect();
+  const t = Math.round((e.clientX - r.left - 40) / 920 * data.t);
+  show(Math.max(0, Math.min(data.turns.length - 1, t)));
+};
+show(0);
+</script>
+</body>
+</html>
+"##;
@@ -0,0 +1,209 @@
+use crate::def::*;
+use crate::expectimax::*;
+use crate::param_table::*;
+use crate::project_model::*;
+use crate::simulator::*;
+use crate::solver::*;
+use crate::trace::*;
+
+// あるターンの行動の差し替え、指定しないものは記録の通り
+// カードを変えて対象を指定しない場合は、そのカードに対するソルバーの対象を使う
+// 使うカードか対象を変えて補充を指定しない場合は、ソルバーが補充を選び直す
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Override {
+    pub c: Option<usize>,
+    pub m: Option<usize>,
+    pub r: Option<usize>,
+}
+
+impl Override {
+    // "c=1 m=0 r=2" の形式
+    pub fn parse(args: &[String]) -> Result<Override, String> {
+        let mut o = Override::default();
+        for arg in args {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("invalid override: `{arg}`"))?;
+            let value = value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid override: `{arg}`"))?;
+            match key {
+                "c" => o.c = Some(value),
+                "m" => o.m = Some(value),
+                "r" => o.r = Some(value),
+                _ => return Err(format!("invalid override key: `{key}`")),
+            }
+        }
+        Ok(o)
+    }
+}
+
+// ゲームをソルバーで解いた記録から、turnの行動だけを差し替えて同じ乱数列で最後まで進め、最終スコアの差を表示する
+// ゲームはseedか入力ファイル（隠れた乱数列を含む形式）、trace=<file>を付けると解き直さずにそのゲームの記録を使う
+// 比較対象は記録の行動のまま同じ方法で進めたもの（終盤のモンテカルロの乱数がずれるので、元のスコアとは少しずれることがある）
+// usage: whatif <seed|game> <turn> [trace=<file>] [c=<card>] [m=<project>] [r=<refill>]
+pub fn run(args: &[String], param_table: &ParamTable, evaluator: &Evaluator) {
+    let source = args.first().map(|s| s.as_str()).unwrap_or("0");
+    let turn: usize = args.get(1).map(|s| s.parse().unwrap()).unwrap_or(0);
+    let rest = args.get(2..).unwrap_or(&[]);
+    let trace_path = rest.iter().find_map(|arg| arg.strip_prefix("trace="));
+    let overrides: Vec<String> = rest
+        .iter()
+        .filter(|arg| !arg.starts_with("trace="))
+        .cloned()
+        .collect();
+    let o = Override::parse(&overrides).unwrap();
+
+    let game = match Game::from_source(source) {
+        Ok(game) => game,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    let new_solver = || Solver {
+        state: game.initial_state(),
+        param: param_table.lookup(&game.input),
+        project_model: ProjectModel::new(),
+        evaluator: evaluator.clone(),
+        verbosity: 0,
+        policy: Policy::Greedy,
+    };
+    let trace = match trace_path {
+        Some(path) => match Trace::load(path) {
+            Ok(trace) => trace,
+            Err(e) => {
+                eprintln!("{e}");
+                return;
+            }
+        },
+        None => new_solver().solve(&game.input, &mut MockInteractor::from_game(&game)),
+    };
+    if turn >= trace.turns.len() {
+        eprintln!("turn {} is out of range (0..{})", turn, trace.turns.len());
+        return;
+    }
+    let record = &trace.turns[turn];
+
+    let control = match replay(&game, &trace, turn, Override::default(), &mut new_solver()) {
+        Ok(score) => score,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    let score = match replay(&game, &trace, turn, o, &mut new_solver()) {
+        Ok(score) => score,
+        Err(e) => {
+            eprintln!("invalid override: {e}");
+            return;
+        }
+    };
+    println!(
+        "turn {}: recorded use {} on {}, refill {}",
+        turn,
+        record.action.slot().unwrap(),
+        record.action.project(),
+        record.refill
+    );
+    println!("original: {}", trace.score);
+    println!("recorded: {}", control);
+    println!("override: {}", score);
+    println!(
+        "diff: {} ({:+.4} in log score)",
+        score - control,
+        (score.max(1) as f64).ln() - (control.max(1) as f64).ln()
+    );
+}
+
+// turnの前まで記録の通りに進め、turnは差し替えた行動、その後はソルバーで進めた最終スコア
+// 差し替えたターンの補充の候補はオーバーライドしても同じなので、xはturnまでの記録から数える
+pub fn replay(
+    game: &Game,
+    trace: &Trace,
+    turn: usize,
+    o: Override,
+    solver: &mut Solver,
+) -> Result<i64, String> {
+    let input = &game.input;
+    let mut interactor = MockInteractor::from_game(game);
+    let mut x = vec![0; 5];
+    for offer in trace.turns[..=turn]
+        .iter()
+        .flat_map(|turn| turn.offers.iter())
+    {
+        x[offer.card.to_t()] += 1;
+    }
+    // 記録が別のゲームのものだと、どこかのターンで状態が食い違う
+    for record in trace.turns[..=turn].iter() {
+        if solver.state.score != record.money
+            || solver.state.cards != record.cards
+            || solver.state.projects != record.projects
+        {
+            return Err(format!(
+                "replay diverged from the trace at turn {}",
+                record.t
+            ));
+        }
+        if record.t == turn {
+            break;
+        }
+        record.action.validate(input, &solver.state, &[])?;
+        if let Action::Invest { .. } = record.action {
+            solver.state.last_invest_round = record.t;
+        }
+        solver.state.use_card(record.action, &mut interactor);
+        let new_cards = solver.state.read_status(input, &mut interactor);
+        Action::Refill {
+            offer: record.refill,
+        }
+        .validate(input, &solver.state, &new_cards)?;
+        solver
+            .state
+            .refill_card(record.refill, &new_cards, &mut interactor);
+    }
+    let record = &trace.turns[turn];
+
+    let c = o.c.unwrap_or(record.action.slot().unwrap());
+    if c >= input.n {
+        return Err(format!("card {c} is out of range (0..{})", input.n));
+    }
+    let card = solver.state.cards[c];
+    let m = match (o.m, o.c) {
+        (Some(m), _) => m,
+        (None, Some(_)) => solver.eval(&card, turn).1,
+        (None, None) => record.action.project(),
+    };
+    let action = Action::use_card(c, card, m);
+    action.validate(input, &solver.state, &[])?;
+    if let Action::Invest { .. } = action {
+        solver.state.last_invest_round = turn;
+    }
+    solver.state.use_card(action, &mut interactor);
+    let new_cards = solver.state.read_status(input, &mut interactor);
+
+    let r = match o.r {
+        Some(r) => r,
+        None if o.c.is_none() && o.m.is_none() => record.refill,
+        None => solver.select_refill(input, &new_cards, turn, &x),
+    };
+    Action::Refill { offer: r }.validate(input, &solver.state, &new_cards)?;
+    solver.state.refill_card(r, &new_cards, &mut interactor);
+
+    for t in turn + 1..input.t {
+        let action = solver.select_use_card(t);
+        if let Action::Invest { .. } = action {
+            solver.state.last_invest_round = t;
+        }
+        solver.state.use_card(action, &mut interactor);
+        let new_cards = solver.state.read_status(input, &mut interactor);
+        for offer in new_cards.iter() {
+            x[offer.card.to_t()] += 1;
+        }
+        let new_card = solver.select_refill(input, &new_cards, t, &x);
+        solver
+            .state
+            .refill_card(new_card, &new_cards, &mut interactor);
+    }
+    Ok(solver.state.score)
+}